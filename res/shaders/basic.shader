@@ -6,10 +6,10 @@ layout(location = 1) in vec2 tex_coord;
 
 out vec2 v_tex_coord;
 
-uniform mat4 u_mvp;
+#include "common/transform.glsl"
 
 void main() {
-    gl_Position = u_mvp * position;
+    gl_Position = transform(position);
     v_tex_coord = tex_coord;
 }
 
@@ -25,6 +25,9 @@ uniform vec4 u_color;
 uniform sampler2D u_texture;
 
 void main() {
-    vec4 tex_color = texture(u_texture, v_tex_coord);
-    color = tex_color;
+#ifdef SOLID_COLOR
+    color = u_color;
+#else
+    color = texture(u_texture, v_tex_coord);
+#endif
 }
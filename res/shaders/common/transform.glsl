@@ -0,0 +1,12 @@
+#pragma once
+
+layout(std140) uniform Camera {
+    mat4 u_proj;
+    mat4 u_view;
+};
+
+uniform mat4 u_model;
+
+vec4 transform(vec4 position) {
+    return u_proj * u_view * u_model * position;
+}
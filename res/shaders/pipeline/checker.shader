@@ -0,0 +1,14 @@
+#shader fragment
+#version 410 core
+
+layout(location = 0) in vec2 v_tex_coord;
+
+layout(location = 0) out vec4 color;
+
+uniform float u_cells;
+
+void main() {
+    vec2 cell = floor(v_tex_coord * u_cells);
+    float checker = mod(cell.x + cell.y, 2.0);
+    color = vec4(vec3(0.2 + 0.6 * checker), 1.0);
+}
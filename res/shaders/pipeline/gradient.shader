@@ -0,0 +1,10 @@
+#shader fragment
+#version 410 core
+
+layout(location = 0) in vec2 v_tex_coord;
+
+layout(location = 0) out vec4 color;
+
+void main() {
+    color = vec4(v_tex_coord, 0.5, 1.0);
+}
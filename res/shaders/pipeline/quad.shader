@@ -0,0 +1,18 @@
+#shader vertex
+#version 410 core
+
+layout(location = 0) in vec4 position;
+layout(location = 1) in vec2 tex_coord;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location = 0) out vec2 v_tex_coord;
+
+#include "../common/transform.glsl"
+
+void main() {
+    gl_Position = transform(position);
+    v_tex_coord = tex_coord;
+}
@@ -0,0 +1,33 @@
+#shader vertex
+#version 450 core
+
+layout(location = 0) in vec4 position;
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 u_proj;
+    mat4 u_view;
+};
+
+layout(std140, binding = 1) uniform Model {
+    mat4 u_model;
+    vec4 u_color;
+};
+
+void main() {
+    gl_Position = u_proj * u_view * u_model * position;
+}
+
+
+#shader fragment
+#version 450 core
+
+layout(std140, binding = 1) uniform Model {
+    mat4 u_model;
+    vec4 u_color;
+};
+
+layout(location = 0) out vec4 color;
+
+void main() {
+    color = u_color;
+}
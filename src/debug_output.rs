@@ -0,0 +1,228 @@
+use super::{gl_backend, glcall};
+
+use std::{
+    collections::HashSet,
+    ffi::{c_void, CStr},
+    fmt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl From<u32> for DebugSeverity {
+    fn from(severity: u32) -> Self {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+            _ => DebugSeverity::Notification,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+impl From<u32> for DebugSource {
+    fn from(source: u32) -> Self {
+        match source {
+            gl::DEBUG_SOURCE_API => DebugSource::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+            _ => DebugSource::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+impl From<u32> for DebugType {
+    fn from(msg_type: u32) -> Self {
+        match msg_type {
+            gl::DEBUG_TYPE_ERROR => DebugType::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+            gl::DEBUG_TYPE_MARKER => DebugType::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+            _ => DebugType::Other,
+        }
+    }
+}
+
+pub struct DebugMessage<'a> {
+    pub source: DebugSource,
+    pub msg_type: DebugType,
+    pub id: u32,
+    pub severity: DebugSeverity,
+    pub text: &'a str,
+}
+
+impl fmt::Display for DebugMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "OpenGL {:?} [{:?}/{:?}] (id {}): {}",
+            self.severity, self.source, self.msg_type, self.id, self.text
+        )
+    }
+}
+
+/// Receives every message that passes the severity filter and is not
+/// suppressed.
+pub trait DebugLogger {
+    fn log(&mut self, message: &DebugMessage);
+}
+
+pub struct StdoutLogger;
+
+impl DebugLogger for StdoutLogger {
+    fn log(&mut self, message: &DebugMessage) {
+        println!("{}", message);
+    }
+}
+
+struct CallbackState {
+    logger: Box<dyn DebugLogger>,
+    min_severity: DebugSeverity,
+    suppressed: HashSet<u32>,
+}
+
+extern "system" fn debug_callback(
+    source: u32,
+    msg_type: u32,
+    id: u32,
+    severity: u32,
+    length: i32,
+    message: *const i8,
+    user_param: *mut c_void,
+) {
+    let state = unsafe { &mut *(user_param as *mut CallbackState) };
+    //
+    if msg_type == gl::DEBUG_TYPE_ERROR {
+        glcall::set_error_reported();
+    }
+    //
+    let severity = DebugSeverity::from(severity);
+    if severity < state.min_severity || state.suppressed.contains(&id) {
+        return;
+    }
+    //
+    let text = if length < 0 {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy()
+    } else {
+        String::from_utf8_lossy(unsafe {
+            std::slice::from_raw_parts(message as *const u8, length as usize)
+        })
+    };
+    //
+    state.logger.log(&DebugMessage {
+        source: DebugSource::from(source),
+        msg_type: DebugType::from(msg_type),
+        id: id,
+        severity: severity,
+        text: text.trim_end(),
+    });
+}
+
+/// An installed `glDebugMessageCallback`. While it is alive `gl_call!` only
+/// polls `glGetError` after calls the driver reported an error for, dropping
+/// it restores polling after every call.
+pub struct DebugOutput {
+    state: Box<CallbackState>,
+    /// GL 4.3 or KHR_debug, which has `GL_DEBUG_OUTPUT` to switch on.
+    khr_debug: bool,
+}
+
+impl DebugOutput {
+    /// Returns `None` when neither GL 4.3 nor KHR_debug/ARB_debug_output is
+    /// available, in which case errors keep being polled.
+    pub fn install(logger: Box<dyn DebugLogger>) -> Option<Self> {
+        let khr_debug = gl_backend::gl_version() >= (4, 3)
+            || gl_backend::has_extension("GL_KHR_debug");
+        if !khr_debug && !gl_backend::has_extension("GL_ARB_debug_output") {
+            return None;
+        }
+        //
+        let mut state = Box::new(CallbackState {
+            logger: logger,
+            min_severity: DebugSeverity::Notification,
+            suppressed: HashSet::new(),
+        });
+        //
+        // ARB_debug_output is always enabled
+        if khr_debug {
+            gl_call!(gl::Enable(gl::DEBUG_OUTPUT));
+        }
+        gl_call!(gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS));
+        gl_call!(gl::DebugMessageCallback(
+            Some(debug_callback),
+            &mut *state as *mut CallbackState as *const c_void
+        ));
+        gl_call!(gl::DebugMessageControl(
+            gl::DONT_CARE,
+            gl::DONT_CARE,
+            gl::DONT_CARE,
+            0,
+            std::ptr::null(),
+            gl::TRUE
+        ));
+        //
+        glcall::set_polling_enabled(false);
+        //
+        Some(Self {
+            state: state,
+            khr_debug: khr_debug,
+        })
+    }
+    //
+    pub fn set_min_severity(&mut self, severity: DebugSeverity) {
+        self.state.min_severity = severity;
+    }
+    //
+    pub fn suppress(&mut self, id: u32) {
+        self.state.suppressed.insert(id);
+    }
+    //
+    pub fn unsuppress(&mut self, id: u32) {
+        self.state.suppressed.remove(&id);
+    }
+}
+
+impl Drop for DebugOutput {
+    fn drop(&mut self) {
+        glcall::set_polling_enabled(true);
+        gl_call!(gl::DebugMessageCallback(None, std::ptr::null()));
+        gl_call!(gl::Disable(gl::DEBUG_OUTPUT_SYNCHRONOUS));
+        if self.khr_debug {
+            gl_call!(gl::Disable(gl::DEBUG_OUTPUT));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::Instant;
+
+/// Upper bound on fixed steps per frame, so a long stall (a breakpoint, a
+/// window drag) does not make the simulation spiral trying to catch up.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Measures frame deltas and turns them into the `on_update` calls for a
+/// frame, applying pause, time scaling and optional fixed-timestep
+/// accumulation.
+pub struct FrameClock {
+    last_tick: Option<Instant>,
+    fixed_delta: Option<f32>,
+    fixed_step: Option<f32>,
+    accumulator: f32,
+    frame_delta: f32,
+    pub time_scale: f32,
+    pub paused: bool,
+}
+
+/// The update deltas for one frame.
+pub struct Updates {
+    delta_time: f32,
+    remaining: u32,
+}
+
+impl Iterator for Updates {
+    type Item = f32;
+    //
+    fn next(&mut self) -> Option<f32> {
+        if self.remaining == 0 {
+            None
+        } else {
+            self.remaining -= 1;
+            Some(self.delta_time)
+        }
+    }
+}
+
+impl FrameClock {
+    /// Measures real time between ticks.
+    pub fn new() -> Self {
+        Self {
+            last_tick: None,
+            fixed_delta: None,
+            fixed_step: None,
+            accumulator: 0.0,
+            frame_delta: 0.0,
+            time_scale: 1.0,
+            paused: false,
+        }
+    }
+    //
+    /// Pretends every frame took exactly `delta_time`, for headless and
+    /// golden runs that must not depend on how fast the machine is.
+    pub fn fixed(delta_time: f32) -> Self {
+        Self {
+            fixed_delta: Some(delta_time),
+            ..Self::new()
+        }
+    }
+    //
+    /// With `Some(step)` the scaled frame time is accumulated and handed
+    /// out as whole steps of `step` seconds.
+    pub fn set_fixed_step(&mut self, step: Option<f32>) {
+        self.fixed_step = step;
+        self.accumulator = 0.0;
+    }
+    //
+    pub fn fixed_step(&self) -> Option<f32> {
+        self.fixed_step
+    }
+    //
+    /// Unscaled duration of the last frame, also while paused.
+    pub fn frame_delta(&self) -> f32 {
+        self.frame_delta
+    }
+    //
+    /// Call once per frame. The first frame of a real-time clock has a
+    /// delta of zero.
+    pub fn tick(&mut self) -> Updates {
+        let now = Instant::now();
+        let frame_delta = match (self.fixed_delta, self.last_tick) {
+            (Some(delta_time), _) => delta_time,
+            (None, Some(last_tick)) => (now - last_tick).as_secs_f32(),
+            (None, None) => 0.0,
+        };
+        self.last_tick = Some(now);
+        self.advance(frame_delta)
+    }
+    //
+    /// Applies a frame that took `frame_delta` seconds.
+    fn advance(&mut self, frame_delta: f32) -> Updates {
+        self.frame_delta = frame_delta;
+        let scaled = if self.paused {
+            0.0
+        } else {
+            self.frame_delta * self.time_scale
+        };
+        //
+        match self.fixed_step {
+            Some(step) => {
+                self.accumulator += scaled;
+                let steps =
+                    ((self.accumulator / step) as u32).min(MAX_STEPS_PER_FRAME);
+                self.accumulator =
+                    (self.accumulator - steps as f32 * step).min(step);
+                Updates {
+                    delta_time: step,
+                    remaining: steps,
+                }
+            }
+            None => Updates {
+                delta_time: scaled,
+                remaining: 1,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn updates(clock: &mut FrameClock, frame_delta: f32) -> Vec<f32> {
+        clock.advance(frame_delta).collect()
+    }
+
+    #[test]
+    fn first_real_time_tick_is_zero() {
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.tick().collect::<Vec<_>>(), [0.0]);
+        assert_eq!(clock.frame_delta(), 0.0);
+    }
+
+    #[test]
+    fn fixed_clock_repeats_its_delta() {
+        let mut clock = FrameClock::fixed(0.25);
+        for _ in 0..3 {
+            assert_eq!(clock.tick().collect::<Vec<_>>(), [0.25]);
+        }
+    }
+
+    #[test]
+    fn time_scale_and_pause() {
+        let mut clock = FrameClock::new();
+        clock.time_scale = 2.0;
+        assert_eq!(updates(&mut clock, 0.25), [0.5]);
+        //
+        clock.paused = true;
+        assert_eq!(updates(&mut clock, 0.25), [0.0]);
+        assert_eq!(clock.frame_delta(), 0.25);
+    }
+
+    #[test]
+    fn fixed_step_accumulates() {
+        let mut clock = FrameClock::new();
+        clock.set_fixed_step(Some(0.25));
+        assert_eq!(updates(&mut clock, 0.625), [0.25, 0.25]);
+        // 0.125 left over from the previous frame
+        assert_eq!(updates(&mut clock, 0.125), [0.25]);
+        assert_eq!(updates(&mut clock, 0.125), []);
+        //
+        clock.time_scale = 0.5;
+        assert_eq!(updates(&mut clock, 0.25), [0.25]);
+    }
+
+    #[test]
+    fn fixed_step_is_clamped() {
+        let mut clock = FrameClock::new();
+        clock.set_fixed_step(Some(0.25));
+        assert_eq!(
+            updates(&mut clock, 10.0),
+            [0.25; MAX_STEPS_PER_FRAME as usize]
+        );
+        // At most one step is carried over
+        assert_eq!(updates(&mut clock, 0.0), [0.25]);
+        assert_eq!(updates(&mut clock, 0.0), []);
+    }
+
+    #[test]
+    fn paused_fixed_step_does_not_advance() {
+        let mut clock = FrameClock::new();
+        clock.set_fixed_step(Some(0.25));
+        clock.paused = true;
+        assert_eq!(updates(&mut clock, 1.0), []);
+        //
+        clock.paused = false;
+        assert_eq!(updates(&mut clock, 0.25), [0.25]);
+    }
+}
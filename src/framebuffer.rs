@@ -0,0 +1,369 @@
+use super::{gl_backend, glcall};
+
+use std::{cell::Cell, error::Error, fmt};
+
+use super::{GlCallError, Texture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthStencilFormat {
+    Depth16,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+    Depth32FStencil8,
+}
+
+impl DepthStencilFormat {
+    fn internal_format(&self) -> u32 {
+        match self {
+            DepthStencilFormat::Depth16 => gl::DEPTH_COMPONENT16,
+            DepthStencilFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            DepthStencilFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            DepthStencilFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            DepthStencilFormat::Depth32FStencil8 => gl::DEPTH32F_STENCIL8,
+        }
+    }
+    //
+    fn attachment(&self) -> u32 {
+        match self {
+            DepthStencilFormat::Depth24Stencil8
+            | DepthStencilFormat::Depth32FStencil8 => {
+                gl::DEPTH_STENCIL_ATTACHMENT
+            }
+            _ => gl::DEPTH_ATTACHMENT,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FramebufferError {
+    Undefined,
+    IncompleteAttachment,
+    MissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(u32),
+    /// A color format textures cannot be created with.
+    UnsupportedFormat(u32),
+    Gl(GlCallError),
+}
+
+impl From<u32> for FramebufferError {
+    fn from(status: u32) -> Self {
+        match status {
+            gl::FRAMEBUFFER_UNDEFINED => FramebufferError::Undefined,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => {
+                FramebufferError::IncompleteAttachment
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+                FramebufferError::MissingAttachment
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => {
+                FramebufferError::IncompleteDrawBuffer
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => {
+                FramebufferError::IncompleteReadBuffer
+            }
+            gl::FRAMEBUFFER_UNSUPPORTED => FramebufferError::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => {
+                FramebufferError::IncompleteMultisample
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => {
+                FramebufferError::IncompleteLayerTargets
+            }
+            status => FramebufferError::Unknown(status),
+        }
+    }
+}
+
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FramebufferError::Undefined => {
+                write!(f, "the default framebuffer does not exist")
+            }
+            FramebufferError::IncompleteAttachment => write!(
+                f,
+                "an attachment is incomplete or has a zero size or an \
+                 unrenderable format"
+            ),
+            FramebufferError::MissingAttachment => {
+                write!(f, "the framebuffer has no attachments")
+            }
+            FramebufferError::IncompleteDrawBuffer => {
+                write!(f, "a draw buffer names an attachment that is missing")
+            }
+            FramebufferError::IncompleteReadBuffer => {
+                write!(f, "the read buffer names an attachment that is missing")
+            }
+            FramebufferError::Unsupported => write!(
+                f,
+                "the driver does not support this combination of \
+                 attachment formats"
+            ),
+            FramebufferError::IncompleteMultisample => {
+                write!(f, "attachments have mismatched sample counts")
+            }
+            FramebufferError::IncompleteLayerTargets => {
+                write!(f, "attachments are not all layered the same way")
+            }
+            FramebufferError::Unknown(status) => {
+                write!(f, "unknown framebuffer status 0x{:x}", status)
+            }
+            FramebufferError::UnsupportedFormat(format) => {
+                write!(f, "unsupported color format 0x{:x}", format)
+            }
+            FramebufferError::Gl(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for FramebufferError {}
+
+impl From<GlCallError> for FramebufferError {
+    fn from(error: GlCallError) -> Self {
+        FramebufferError::Gl(error)
+    }
+}
+
+/// An offscreen render target with texture color attachments, so a later
+/// pass can sample what was rendered, and an optional depth/stencil
+/// renderbuffer.
+/// The draw and read framebuffer bindings, which binding `GL_FRAMEBUFFER`
+/// replaces together.
+#[derive(Debug, Clone, Copy)]
+struct Bindings {
+    draw: u32,
+    read: u32,
+}
+
+impl Bindings {
+    fn current() -> Self {
+        let backend = gl_backend::current();
+        Self {
+            draw: backend.get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as u32,
+            read: backend.get_integer(gl::READ_FRAMEBUFFER_BINDING) as u32,
+        }
+    }
+    //
+    fn restore(&self) {
+        let backend = gl_backend::current();
+        backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.draw);
+        backend.bind_framebuffer(gl::READ_FRAMEBUFFER, self.read);
+    }
+}
+
+pub struct Framebuffer {
+    renderer_id: u32,
+    color_formats: Vec<u32>,
+    color_attachments: Vec<Texture>,
+    depth_stencil_format: Option<DepthStencilFormat>,
+    depth_stencil_id: u32,
+    width: u32,
+    height: u32,
+    /// The framebuffer bindings and viewport from before `bind`.
+    saved_state: Cell<Option<(Bindings, [i32; 4])>>,
+}
+
+impl Framebuffer {
+    pub fn new(
+        width: u32,
+        height: u32,
+        color_formats: &[u32],
+        depth_stencil_format: Option<DepthStencilFormat>,
+    ) -> Result<Self, FramebufferError> {
+        let unsupported = color_formats
+            .iter()
+            .find(|&&format| !Texture::supports_format(format));
+        if let Some(&format) = unsupported {
+            return Err(FramebufferError::UnsupportedFormat(format));
+        }
+        //
+        glcall::try_create(|| {
+            let mut framebuffer = Self {
+                renderer_id: gl_backend::current().gen_framebuffer(),
+                color_formats: color_formats.to_vec(),
+                color_attachments: Vec::new(),
+                depth_stencil_format: depth_stencil_format,
+                depth_stencil_id: 0,
+                width: width,
+                height: height,
+                saved_state: Cell::new(None),
+            };
+            framebuffer.create_attachments()?;
+            //
+            Ok(framebuffer)
+        })?
+    }
+    //
+    fn create_attachments(&mut self) -> Result<(), FramebufferError> {
+        let backend = gl_backend::current();
+        let previous = Bindings::current();
+        backend.bind_framebuffer(gl::FRAMEBUFFER, self.renderer_id);
+        //
+        self.color_attachments = self
+            .color_formats
+            .iter()
+            .enumerate()
+            .map(|(i, &format)| {
+                let texture = Texture::new(self.width, self.height, format)?;
+                backend.framebuffer_texture_2d(
+                    gl::COLOR_ATTACHMENT0 + i as u32,
+                    texture.renderer_id(),
+                );
+                Ok(texture)
+            })
+            .collect::<Result<_, GlCallError>>()?;
+        //
+        let draw_buffers: Vec<_> = (0..self.color_attachments.len() as u32)
+            .map(|i| gl::COLOR_ATTACHMENT0 + i)
+            .collect();
+        backend.draw_buffers(&draw_buffers);
+        //
+        if let Some(format) = self.depth_stencil_format {
+            self.depth_stencil_id = backend.gen_renderbuffer();
+            backend.bind_renderbuffer(self.depth_stencil_id);
+            backend.renderbuffer_storage(
+                format.internal_format(),
+                self.width as i32,
+                self.height as i32,
+            );
+            backend.bind_renderbuffer(0);
+            backend.framebuffer_renderbuffer(
+                format.attachment(),
+                self.depth_stencil_id,
+            );
+        }
+        //
+        let status = backend.check_framebuffer_status();
+        previous.restore();
+        //
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(FramebufferError::from(status))
+        }
+    }
+    //
+    fn delete_depth_stencil(&mut self) {
+        if self.depth_stencil_id != 0 {
+            gl_backend::current().delete_renderbuffer(self.depth_stencil_id);
+            self.depth_stencil_id = 0;
+        }
+    }
+    //
+    /// Recreates every attachment at the new size. Their previous contents
+    /// are lost.
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<(), FramebufferError> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+        //
+        self.delete_depth_stencil();
+        self.width = width;
+        self.height = height;
+        self.create_attachments()
+    }
+    //
+    /// Binds the framebuffer for drawing and reading and sets the viewport
+    /// to cover it, remembering the previous bindings and viewport for
+    /// `unbind`.
+    pub fn bind(&self) {
+        let backend = gl_backend::current();
+        if self.saved_state.get().is_none() {
+            self.saved_state
+                .set(Some((Bindings::current(), backend.get_viewport())));
+        }
+        backend.bind_framebuffer(gl::FRAMEBUFFER, self.renderer_id);
+        backend.viewport([0, 0, self.width as i32, self.height as i32]);
+    }
+    //
+    /// Rebinds whatever was bound before `bind`, the default framebuffer if
+    /// it was not called.
+    pub fn unbind(&self) {
+        let backend = gl_backend::current();
+        match self.saved_state.take() {
+            Some((bindings, viewport)) => {
+                bindings.restore();
+                backend.viewport(viewport);
+            }
+            None => backend.bind_framebuffer(gl::FRAMEBUFFER, 0),
+        }
+    }
+    //
+    pub fn color_attachment(&self, index: usize) -> &Texture {
+        &self.color_attachments[index]
+    }
+    //
+    pub fn renderer_id(&self) -> u32 {
+        self.renderer_id
+    }
+    //
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    //
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        self.delete_depth_stencil();
+        gl_backend::current().delete_framebuffer(self.renderer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+
+    #[test]
+    fn framebuffer_restores_binding() {
+        let mock = install_mock();
+        // An offscreen target of the caller, like the headless one
+        mock.set_query_result(gl::DRAW_FRAMEBUFFER_BINDING, 7);
+        mock.set_query_result(gl::READ_FRAMEBUFFER_BINDING, 5);
+        let framebuffer =
+            Framebuffer::new(4, 2, &[gl::SRGB8_ALPHA8], None).unwrap();
+        framebuffer.bind();
+        framebuffer.unbind();
+        //
+        let binds: Vec<_> = call_log(&mock)
+            .into_iter()
+            .filter(|call| call.starts_with("BindFramebuffer"))
+            .collect();
+        assert_eq!(
+            binds,
+            [
+                "BindFramebuffer(0x8d40, 1)",
+                "BindFramebuffer(0x8ca9, 7)",
+                "BindFramebuffer(0x8ca8, 5)",
+                "BindFramebuffer(0x8d40, 1)",
+                "BindFramebuffer(0x8ca9, 7)",
+                "BindFramebuffer(0x8ca8, 5)",
+            ]
+        );
+    }
+
+    #[test]
+    fn framebuffer_rejects_unsupported_format() {
+        install_mock();
+        match Framebuffer::new(4, 2, &[gl::DEPTH_COMPONENT24], None) {
+            Err(FramebufferError::UnsupportedFormat(format)) => {
+                assert_eq!(format, gl::DEPTH_COMPONENT24)
+            }
+            _ => panic!("a depth format was accepted as a color format"),
+        }
+    }
+}
@@ -0,0 +1,1997 @@
+use super::glcall;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::{c_void, CStr, CString},
+    fmt::Write,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// `GL_ARB_gl_spirv` is newer than the `gl` bindings, so its enums and
+/// `glSpecializeShader` are defined and loaded here.
+pub const SHADER_BINARY_FORMAT_SPIR_V: u32 = 0x9551;
+
+type SpecializeShaderFn = unsafe extern "system" fn(
+    shader: u32,
+    entry_point: *const i8,
+    num_constants: u32,
+    constant_indices: *const u32,
+    constant_values: *const u32,
+);
+
+static SPECIALIZE_SHADER: AtomicUsize = AtomicUsize::new(0);
+
+fn specialize_shader_fn() -> Option<SpecializeShaderFn> {
+    match SPECIALIZE_SHADER.load(Ordering::Relaxed) {
+        0 => None,
+        ptr => Some(unsafe { std::mem::transmute(ptr) }),
+    }
+}
+
+/// Loads the `gl` bindings and the entry points they are missing.
+pub fn load_with<F: FnMut(&'static str) -> *const c_void>(mut loadfn: F) {
+    gl::load_with(&mut loadfn);
+    //
+    let ptr = ["glSpecializeShader", "glSpecializeShaderARB"]
+        .iter()
+        .map(|&name| loadfn(name))
+        .find(|ptr| !ptr.is_null())
+        .unwrap_or(std::ptr::null());
+    SPECIALIZE_SHADER.store(ptr as usize, Ordering::Relaxed);
+}
+
+/// The GL entry points used by the resource types. Everything goes through
+/// the backend that is current on the calling thread, which is the real
+/// driver unless a test swaps in a `MockGl`.
+pub trait GlBackend {
+    fn gen_buffer(&self) -> u32;
+    fn bind_buffer(&self, target: u32, id: u32);
+    fn buffer_data(&self, target: u32, data: &[u8], usage: u32);
+    fn buffer_sub_data(&self, target: u32, offset: usize, data: &[u8]);
+    /// `glBufferData` with no data, leaving `size` bytes uninitialized.
+    fn allocate_buffer(&self, target: u32, size: usize, usage: u32);
+    fn copy_buffer_sub_data(
+        &self,
+        read_target: u32,
+        write_target: u32,
+        read_offset: usize,
+        write_offset: usize,
+        size: usize,
+    );
+    fn bind_buffer_base(&self, target: u32, index: u32, id: u32);
+    /// Null when the mapping failed.
+    fn map_buffer_range(
+        &self,
+        target: u32,
+        offset: usize,
+        length: usize,
+        access: u32,
+    ) -> *mut c_void;
+    /// False if the contents were lost while mapped and must be rewritten.
+    fn unmap_buffer(&self, target: u32) -> bool;
+    /// Immutable storage, or false without GL 4.4 or `GL_ARB_buffer_storage`
+    /// or when the call fails.
+    fn buffer_storage(&self, target: u32, size: usize, flags: u32) -> bool;
+    fn delete_buffer(&self, id: u32);
+    //
+    fn gen_vertex_array(&self) -> u32;
+    fn bind_vertex_array(&self, id: u32);
+    fn enable_vertex_attrib_array(&self, index: u32);
+    fn vertex_attrib_pointer(
+        &self,
+        index: u32,
+        count: i32,
+        el_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: usize,
+    );
+    fn delete_vertex_array(&self, id: u32);
+    //
+    fn create_shader(&self, shader_type: u32) -> u32;
+    fn shader_source(&self, id: u32, source: &str);
+    fn compile_shader(&self, id: u32);
+    fn get_shader_iv(&self, id: u32, pname: u32) -> i32;
+    fn get_shader_info_log(&self, id: u32) -> String;
+    fn delete_shader(&self, id: u32);
+    /// Whether `shader_binary` accepts SPIR-V and `specialize_shader` is
+    /// available.
+    fn supports_spirv(&self) -> bool;
+    fn shader_binary(&self, id: u32, format: u32, binary: &[u8]);
+    /// `glSpecializeShader` with `(constant id, value bits)` pairs.
+    fn specialize_shader(
+        &self,
+        id: u32,
+        entry_point: &str,
+        constants: &[(u32, u32)],
+    );
+    //
+    fn create_program(&self) -> u32;
+    fn attach_shader(&self, program: u32, shader: u32);
+    fn link_program(&self, program: u32);
+    fn validate_program(&self, program: u32);
+    fn get_program_iv(&self, program: u32, pname: u32) -> i32;
+    fn get_program_info_log(&self, program: u32) -> String;
+    fn use_program(&self, program: u32);
+    /// -1 when there is no such uniform, which includes names with a NUL
+    /// before the end.
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32;
+    fn get_active_uniform(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> (String, i32, u32);
+    fn get_active_uniform_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32;
+    fn get_active_uniform_block_name(&self, program: u32, index: u32)
+        -> String;
+    fn get_active_uniform_block_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32;
+    fn get_active_attrib(&self, program: u32, index: u32)
+        -> (String, i32, u32);
+    /// -1 when there is no such attribute, which includes names with a NUL.
+    fn get_attrib_location(&self, program: u32, name: &str) -> i32;
+    fn uniform_block_binding(&self, program: u32, index: u32, binding: u32);
+    /// `glUniform{components}fv`, setting `values.len() / components`
+    /// array elements. `components` is 1 to 4.
+    fn uniform_fv(&self, location: i32, components: i32, values: &[f32]);
+    fn uniform_iv(&self, location: i32, components: i32, values: &[i32]);
+    fn uniform_uiv(&self, location: i32, components: i32, values: &[u32]);
+    /// `glUniformMatrix{columns}x{rows}fv` with column-major `values`. Both
+    /// sizes are 2 to 4.
+    fn uniform_matrix_fv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f32],
+    );
+    /// The `double` versions, which need GL 4.0.
+    fn uniform_dv(&self, location: i32, components: i32, values: &[f64]);
+    fn uniform_matrix_dv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f64],
+    );
+    fn delete_program(&self, program: u32);
+    /// Empty when program binaries are not supported at all.
+    fn program_binary_formats(&self) -> Vec<u32>;
+    fn program_parameter_i(&self, program: u32, pname: u32, value: i32);
+    fn get_program_binary(&self, program: u32) -> (u32, Vec<u8>);
+    /// Fails rather than panics for a binary the driver cannot take, so the
+    /// caller can compile from source instead.
+    fn program_binary(
+        &self,
+        program: u32,
+        format: u32,
+        binary: &[u8],
+    ) -> Result<(), glcall::GlCallError>;
+    //
+    fn gen_program_pipeline(&self) -> u32;
+    fn bind_program_pipeline(&self, pipeline: u32);
+    fn use_program_stages(&self, pipeline: u32, stages: u32, program: u32);
+    fn validate_program_pipeline(&self, pipeline: u32);
+    fn get_program_pipeline_iv(&self, pipeline: u32, pname: u32) -> i32;
+    fn get_program_pipeline_info_log(&self, pipeline: u32) -> String;
+    fn delete_program_pipeline(&self, pipeline: u32);
+    //
+    fn gen_texture(&self) -> u32;
+    fn active_texture(&self, unit: u32);
+    fn bind_texture(&self, target: u32, id: u32);
+    fn tex_parameter_i(&self, target: u32, pname: u32, param: i32);
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        data: Option<&[u8]>,
+    );
+    fn delete_texture(&self, id: u32);
+    //
+    fn draw_elements(&self, mode: u32, count: i32, index_type: u32);
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+    fn enable(&self, cap: u32);
+    fn disable(&self, cap: u32);
+    fn primitive_restart_index(&self, index: u32);
+    fn clear(&self, mask: u32);
+    fn dispatch_compute(&self, x: u32, y: u32, z: u32);
+    fn memory_barrier(&self, barriers: u32);
+    //
+    /// Returns the fence as an opaque handle.
+    fn fence_sync(&self) -> usize;
+    fn client_wait_sync(&self, sync: usize, flags: u32, timeout: u64) -> u32;
+    fn delete_sync(&self, sync: usize);
+    //
+    fn gen_framebuffer(&self) -> u32;
+    fn bind_framebuffer(&self, target: u32, id: u32);
+    fn framebuffer_texture_2d(&self, attachment: u32, texture: u32);
+    fn framebuffer_renderbuffer(&self, attachment: u32, renderbuffer: u32);
+    fn draw_buffers(&self, attachments: &[u32]);
+    fn check_framebuffer_status(&self) -> u32;
+    fn delete_framebuffer(&self, id: u32);
+    //
+    fn gen_renderbuffer(&self) -> u32;
+    fn bind_renderbuffer(&self, id: u32);
+    fn renderbuffer_storage(
+        &self,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    );
+    fn delete_renderbuffer(&self, id: u32);
+    //
+    fn get_integer(&self, pname: u32) -> i32;
+    fn get_string(&self, name: u32) -> String;
+    fn get_viewport(&self) -> [i32; 4];
+    fn viewport(&self, viewport: [i32; 4]);
+    //
+    fn read_buffer(&self, mode: u32);
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        pixels: &mut [u8],
+    );
+}
+
+thread_local! {
+    static CURRENT: RefCell<Rc<dyn GlBackend>> =
+        RefCell::new(Rc::new(NativeGl));
+}
+
+pub fn current() -> Rc<dyn GlBackend> {
+    CURRENT.with(|backend| backend.borrow().clone())
+}
+
+/// Makes `backend` current for this thread and returns the previous one.
+pub fn set_current(backend: Rc<dyn GlBackend>) -> Rc<dyn GlBackend> {
+    CURRENT.with(|current| current.replace(backend))
+}
+
+pub fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            data.as_ptr() as *const u8,
+            std::mem::size_of_val(data),
+        )
+    }
+}
+
+/// The version of the current native context. Entry points are loaded
+/// whenever the driver exports them, so this and `has_extension` tell
+/// whether they may actually be called.
+pub fn gl_version() -> (i32, i32) {
+    let mut major = 0;
+    let mut minor = 0;
+    gl_call!(gl::GetIntegerv(gl::MAJOR_VERSION, &mut major));
+    gl_call!(gl::GetIntegerv(gl::MINOR_VERSION, &mut minor));
+    (major, minor)
+}
+
+/// Whether the current native context lists `name`, e.g. `"GL_KHR_debug"`.
+pub fn has_extension(name: &str) -> bool {
+    let mut count = 0;
+    gl_call!(gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count));
+    (0..count as u32).any(|i| {
+        let ptr = gl_call!(gl::GetStringi(gl::EXTENSIONS, i));
+        !ptr.is_null()
+            && unsafe { CStr::from_ptr(ptr as *const i8) }.to_bytes()
+                == name.as_bytes()
+    })
+}
+
+/// Forwards to the loaded `gl` function pointers.
+pub struct NativeGl;
+
+impl GlBackend for NativeGl {
+    fn gen_buffer(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenBuffers(1, &mut id));
+        id
+    }
+    //
+    fn bind_buffer(&self, target: u32, id: u32) {
+        gl_call!(gl::BindBuffer(target, id));
+    }
+    //
+    fn buffer_data(&self, target: u32, data: &[u8], usage: u32) {
+        gl_call!(gl::BufferData(
+            target,
+            data.len() as isize,
+            data.as_ptr() as *const c_void,
+            usage,
+        ));
+    }
+    //
+    fn buffer_sub_data(&self, target: u32, offset: usize, data: &[u8]) {
+        gl_call!(gl::BufferSubData(
+            target,
+            offset as isize,
+            data.len() as isize,
+            data.as_ptr() as *const c_void,
+        ));
+    }
+    //
+    fn bind_buffer_base(&self, target: u32, index: u32, id: u32) {
+        gl_call!(gl::BindBufferBase(target, index, id));
+    }
+    //
+    fn delete_buffer(&self, id: u32) {
+        gl_call!(gl::DeleteBuffers(1, &id));
+    }
+    //
+    fn gen_vertex_array(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenVertexArrays(1, &mut id));
+        id
+    }
+    //
+    fn bind_vertex_array(&self, id: u32) {
+        gl_call!(gl::BindVertexArray(id));
+    }
+    //
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        gl_call!(gl::EnableVertexAttribArray(index));
+    }
+    //
+    fn vertex_attrib_pointer(
+        &self,
+        index: u32,
+        count: i32,
+        el_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: usize,
+    ) {
+        gl_call!(gl::VertexAttribPointer(
+            index,
+            count,
+            el_type,
+            if normalized { gl::TRUE } else { gl::FALSE },
+            stride,
+            offset as *const c_void,
+        ));
+    }
+    //
+    fn delete_vertex_array(&self, id: u32) {
+        gl_call!(gl::DeleteVertexArrays(1, &id));
+    }
+    //
+    fn create_shader(&self, shader_type: u32) -> u32 {
+        gl_call!(gl::CreateShader(shader_type))
+    }
+    //
+    fn shader_source(&self, id: u32, source: &str) {
+        gl_call!(gl::ShaderSource(
+            id,
+            1,
+            &(source.as_ptr() as *const i8),
+            std::ptr::null()
+        ));
+    }
+    //
+    fn compile_shader(&self, id: u32) {
+        gl_call!(gl::CompileShader(id));
+    }
+    //
+    fn get_shader_iv(&self, id: u32, pname: u32) -> i32 {
+        let mut result = 0;
+        gl_call!(gl::GetShaderiv(id, pname, &mut result));
+        result
+    }
+    //
+    fn get_shader_info_log(&self, id: u32) -> String {
+        let mut length = self.get_shader_iv(id, gl::INFO_LOG_LENGTH);
+        let mut message = vec![0u8; length.max(1) as usize];
+        gl_call!(gl::GetShaderInfoLog(
+            id,
+            message.len() as i32,
+            &mut length,
+            message.as_mut_ptr() as *mut i8
+        ));
+        message.truncate(length as usize);
+        String::from_utf8_lossy(&message).into_owned()
+    }
+    //
+    fn delete_shader(&self, id: u32) {
+        gl_call!(gl::DeleteShader(id));
+    }
+    //
+    fn create_program(&self) -> u32 {
+        gl_call!(gl::CreateProgram())
+    }
+    //
+    fn attach_shader(&self, program: u32, shader: u32) {
+        gl_call!(gl::AttachShader(program, shader));
+    }
+    //
+    fn link_program(&self, program: u32) {
+        gl_call!(gl::LinkProgram(program));
+    }
+    //
+    fn validate_program(&self, program: u32) {
+        gl_call!(gl::ValidateProgram(program));
+    }
+    //
+    fn use_program(&self, program: u32) {
+        gl_call!(gl::UseProgram(program));
+    }
+    //
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32 {
+        match CString::new(name.trim_end_matches('\0')) {
+            Ok(name) => {
+                gl_call!(gl::GetUniformLocation(program, name.as_ptr()))
+            }
+            Err(_) => -1,
+        }
+    }
+    //
+    fn uniform_fv(&self, location: i32, components: i32, values: &[f32]) {
+        let count = values.len() as i32 / components;
+        let ptr = values.as_ptr();
+        match components {
+            1 => gl_call!(gl::Uniform1fv(location, count, ptr)),
+            2 => gl_call!(gl::Uniform2fv(location, count, ptr)),
+            3 => gl_call!(gl::Uniform3fv(location, count, ptr)),
+            4 => gl_call!(gl::Uniform4fv(location, count, ptr)),
+            _ => unreachable!(
+                "a uniform has 1 to 4 components, not {}",
+                components
+            ),
+        }
+    }
+    //
+    fn uniform_iv(&self, location: i32, components: i32, values: &[i32]) {
+        let count = values.len() as i32 / components;
+        let ptr = values.as_ptr();
+        match components {
+            1 => gl_call!(gl::Uniform1iv(location, count, ptr)),
+            2 => gl_call!(gl::Uniform2iv(location, count, ptr)),
+            3 => gl_call!(gl::Uniform3iv(location, count, ptr)),
+            4 => gl_call!(gl::Uniform4iv(location, count, ptr)),
+            _ => unreachable!(
+                "a uniform has 1 to 4 components, not {}",
+                components
+            ),
+        }
+    }
+    //
+    fn uniform_uiv(&self, location: i32, components: i32, values: &[u32]) {
+        let count = values.len() as i32 / components;
+        let ptr = values.as_ptr();
+        match components {
+            1 => gl_call!(gl::Uniform1uiv(location, count, ptr)),
+            2 => gl_call!(gl::Uniform2uiv(location, count, ptr)),
+            3 => gl_call!(gl::Uniform3uiv(location, count, ptr)),
+            4 => gl_call!(gl::Uniform4uiv(location, count, ptr)),
+            _ => unreachable!(
+                "a uniform has 1 to 4 components, not {}",
+                components
+            ),
+        }
+    }
+    //
+    fn uniform_matrix_fv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f32],
+    ) {
+        let count = values.len() as i32 / (columns * rows);
+        let ptr = values.as_ptr();
+        match (columns, rows) {
+            (2, 2) => {
+                gl_call!(gl::UniformMatrix2fv(location, count, gl::FALSE, ptr))
+            }
+            (2, 3) => {
+                gl_call!(gl::UniformMatrix2x3fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (2, 4) => {
+                gl_call!(gl::UniformMatrix2x4fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (3, 2) => {
+                gl_call!(gl::UniformMatrix3x2fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (3, 3) => {
+                gl_call!(gl::UniformMatrix3fv(location, count, gl::FALSE, ptr))
+            }
+            (3, 4) => {
+                gl_call!(gl::UniformMatrix3x4fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 2) => {
+                gl_call!(gl::UniformMatrix4x2fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 3) => {
+                gl_call!(gl::UniformMatrix4x3fv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 4) => {
+                gl_call!(gl::UniformMatrix4fv(location, count, gl::FALSE, ptr))
+            }
+            _ => unreachable!(
+                "a matrix uniform is 2x2 to 4x4, not {}x{}",
+                columns, rows
+            ),
+        }
+    }
+    //
+    fn uniform_dv(&self, location: i32, components: i32, values: &[f64]) {
+        let count = values.len() as i32 / components;
+        let ptr = values.as_ptr();
+        match components {
+            1 => gl_call!(gl::Uniform1dv(location, count, ptr)),
+            2 => gl_call!(gl::Uniform2dv(location, count, ptr)),
+            3 => gl_call!(gl::Uniform3dv(location, count, ptr)),
+            4 => gl_call!(gl::Uniform4dv(location, count, ptr)),
+            _ => unreachable!(
+                "a uniform has 1 to 4 components, not {}",
+                components
+            ),
+        }
+    }
+    //
+    fn uniform_matrix_dv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f64],
+    ) {
+        let count = values.len() as i32 / (columns * rows);
+        let ptr = values.as_ptr();
+        match (columns, rows) {
+            (2, 2) => {
+                gl_call!(gl::UniformMatrix2dv(location, count, gl::FALSE, ptr))
+            }
+            (2, 3) => {
+                gl_call!(gl::UniformMatrix2x3dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (2, 4) => {
+                gl_call!(gl::UniformMatrix2x4dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (3, 2) => {
+                gl_call!(gl::UniformMatrix3x2dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (3, 3) => {
+                gl_call!(gl::UniformMatrix3dv(location, count, gl::FALSE, ptr))
+            }
+            (3, 4) => {
+                gl_call!(gl::UniformMatrix3x4dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 2) => {
+                gl_call!(gl::UniformMatrix4x2dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 3) => {
+                gl_call!(gl::UniformMatrix4x3dv(
+                    location,
+                    count,
+                    gl::FALSE,
+                    ptr
+                ))
+            }
+            (4, 4) => {
+                gl_call!(gl::UniformMatrix4dv(location, count, gl::FALSE, ptr))
+            }
+            _ => unreachable!(
+                "a matrix uniform is 2x2 to 4x4, not {}x{}",
+                columns, rows
+            ),
+        }
+    }
+    //
+    fn delete_program(&self, program: u32) {
+        gl_call!(gl::DeleteProgram(program));
+    }
+    //
+    fn gen_texture(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenTextures(1, &mut id));
+        id
+    }
+    //
+    fn active_texture(&self, unit: u32) {
+        gl_call!(gl::ActiveTexture(unit));
+    }
+    //
+    fn bind_texture(&self, target: u32, id: u32) {
+        gl_call!(gl::BindTexture(target, id));
+    }
+    //
+    fn tex_parameter_i(&self, target: u32, pname: u32, param: i32) {
+        gl_call!(gl::TexParameteri(target, pname, param));
+    }
+    //
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        data: Option<&[u8]>,
+    ) {
+        gl_call!(gl::TexImage2D(
+            target,
+            0,
+            internal_format as i32,
+            width,
+            height,
+            0,
+            format,
+            data_type,
+            data.map_or(std::ptr::null(), |d| d.as_ptr() as *const c_void)
+        ));
+    }
+    //
+    fn delete_texture(&self, id: u32) {
+        gl_call!(gl::DeleteTextures(1, &id));
+    }
+    //
+    fn draw_elements(&self, mode: u32, count: i32, index_type: u32) {
+        gl_call!(gl::DrawElements(mode, count, index_type, std::ptr::null()));
+    }
+    //
+    fn clear(&self, mask: u32) {
+        gl_call!(gl::Clear(mask));
+    }
+    //
+    fn gen_framebuffer(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenFramebuffers(1, &mut id));
+        id
+    }
+    //
+    fn bind_framebuffer(&self, target: u32, id: u32) {
+        gl_call!(gl::BindFramebuffer(target, id));
+    }
+    //
+    fn framebuffer_texture_2d(&self, attachment: u32, texture: u32) {
+        gl_call!(gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            attachment,
+            gl::TEXTURE_2D,
+            texture,
+            0
+        ));
+    }
+    //
+    fn framebuffer_renderbuffer(&self, attachment: u32, renderbuffer: u32) {
+        gl_call!(gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            attachment,
+            gl::RENDERBUFFER,
+            renderbuffer
+        ));
+    }
+    //
+    fn draw_buffers(&self, attachments: &[u32]) {
+        gl_call!(gl::DrawBuffers(
+            attachments.len() as i32,
+            attachments.as_ptr()
+        ));
+    }
+    //
+    fn check_framebuffer_status(&self) -> u32 {
+        gl_call!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER))
+    }
+    //
+    fn delete_framebuffer(&self, id: u32) {
+        gl_call!(gl::DeleteFramebuffers(1, &id));
+    }
+    //
+    fn gen_renderbuffer(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenRenderbuffers(1, &mut id));
+        id
+    }
+    //
+    fn bind_renderbuffer(&self, id: u32) {
+        gl_call!(gl::BindRenderbuffer(gl::RENDERBUFFER, id));
+    }
+    //
+    fn renderbuffer_storage(
+        &self,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    ) {
+        gl_call!(gl::RenderbufferStorage(
+            gl::RENDERBUFFER,
+            internal_format,
+            width,
+            height
+        ));
+    }
+    //
+    fn delete_renderbuffer(&self, id: u32) {
+        gl_call!(gl::DeleteRenderbuffers(1, &id));
+    }
+    //
+    fn get_viewport(&self) -> [i32; 4] {
+        let mut viewport = [0; 4];
+        gl_call!(gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()));
+        viewport
+    }
+    //
+    fn viewport(&self, viewport: [i32; 4]) {
+        gl_call!(gl::Viewport(
+            viewport[0],
+            viewport[1],
+            viewport[2],
+            viewport[3]
+        ));
+    }
+    //
+    fn read_buffer(&self, mode: u32) {
+        gl_call!(gl::ReadBuffer(mode));
+    }
+    //
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        pixels: &mut [u8],
+    ) {
+        gl_call!(gl::PixelStorei(gl::PACK_ALIGNMENT, 1));
+        gl_call!(gl::ReadPixels(
+            x,
+            y,
+            width,
+            height,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut c_void
+        ));
+    }
+    //
+    fn get_integer(&self, pname: u32) -> i32 {
+        let mut value = 0;
+        gl_call!(gl::GetIntegerv(pname, &mut value));
+        value
+    }
+    //
+    fn get_program_iv(&self, program: u32, pname: u32) -> i32 {
+        let mut result = 0;
+        gl_call!(gl::GetProgramiv(program, pname, &mut result));
+        result
+    }
+    //
+    fn get_program_info_log(&self, program: u32) -> String {
+        let mut length = self.get_program_iv(program, gl::INFO_LOG_LENGTH);
+        let mut message = vec![0u8; length.max(1) as usize];
+        gl_call!(gl::GetProgramInfoLog(
+            program,
+            message.len() as i32,
+            &mut length,
+            message.as_mut_ptr() as *mut i8
+        ));
+        message.truncate(length as usize);
+        String::from_utf8_lossy(&message).into_owned()
+    }
+    //
+    fn dispatch_compute(&self, x: u32, y: u32, z: u32) {
+        gl_call!(gl::DispatchCompute(x, y, z));
+    }
+    //
+    fn memory_barrier(&self, barriers: u32) {
+        gl_call!(gl::MemoryBarrier(barriers));
+    }
+    //
+    fn get_active_uniform(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> (String, i32, u32) {
+        let max_length =
+            self.get_program_iv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH);
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let (mut length, mut size, mut gl_type) = (0, 0, 0);
+        gl_call!(gl::GetActiveUniform(
+            program,
+            index,
+            name.len() as i32,
+            &mut length,
+            &mut size,
+            &mut gl_type,
+            name.as_mut_ptr() as *mut i8
+        ));
+        name.truncate(length as usize);
+        (String::from_utf8_lossy(&name).into_owned(), size, gl_type)
+    }
+    //
+    fn get_active_uniform_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32 {
+        let mut result = 0;
+        gl_call!(gl::GetActiveUniformsiv(
+            program,
+            1,
+            &index,
+            pname,
+            &mut result
+        ));
+        result
+    }
+    //
+    fn get_active_uniform_block_name(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> String {
+        let max_length = self.get_active_uniform_block_iv(
+            program,
+            index,
+            gl::UNIFORM_BLOCK_NAME_LENGTH,
+        );
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let mut length = 0;
+        gl_call!(gl::GetActiveUniformBlockName(
+            program,
+            index,
+            name.len() as i32,
+            &mut length,
+            name.as_mut_ptr() as *mut i8
+        ));
+        name.truncate(length as usize);
+        String::from_utf8_lossy(&name).into_owned()
+    }
+    //
+    fn get_active_uniform_block_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32 {
+        let mut result = 0;
+        gl_call!(gl::GetActiveUniformBlockiv(
+            program,
+            index,
+            pname,
+            &mut result
+        ));
+        result
+    }
+    //
+    fn get_active_attrib(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> (String, i32, u32) {
+        let max_length =
+            self.get_program_iv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH);
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let (mut length, mut size, mut gl_type) = (0, 0, 0);
+        gl_call!(gl::GetActiveAttrib(
+            program,
+            index,
+            name.len() as i32,
+            &mut length,
+            &mut size,
+            &mut gl_type,
+            name.as_mut_ptr() as *mut i8
+        ));
+        name.truncate(length as usize);
+        (String::from_utf8_lossy(&name).into_owned(), size, gl_type)
+    }
+    //
+    fn get_attrib_location(&self, program: u32, name: &str) -> i32 {
+        match CString::new(name) {
+            Ok(name) => gl_call!(gl::GetAttribLocation(program, name.as_ptr())),
+            Err(_) => -1,
+        }
+    }
+    //
+    fn uniform_block_binding(&self, program: u32, index: u32, binding: u32) {
+        gl_call!(gl::UniformBlockBinding(program, index, binding));
+    }
+    //
+    fn get_string(&self, name: u32) -> String {
+        let ptr = gl_call!(gl::GetString(name));
+        if ptr.is_null() {
+            return String::new();
+        }
+        unsafe { CStr::from_ptr(ptr as *const i8) }
+            .to_string_lossy()
+            .into_owned()
+    }
+    //
+    fn program_binary_formats(&self) -> Vec<u32> {
+        // The enums are only valid where the entry points exist
+        if !gl::GetProgramBinary::is_loaded() {
+            return Vec::new();
+        }
+        let count = self.get_integer(gl::NUM_PROGRAM_BINARY_FORMATS);
+        let mut formats = vec![0; count.max(0) as usize];
+        if !formats.is_empty() {
+            gl_call!(gl::GetIntegerv(
+                gl::PROGRAM_BINARY_FORMATS,
+                formats.as_mut_ptr()
+            ));
+        }
+        formats.into_iter().map(|format| format as u32).collect()
+    }
+    //
+    fn program_parameter_i(&self, program: u32, pname: u32, value: i32) {
+        gl_call!(gl::ProgramParameteri(program, pname, value));
+    }
+    //
+    fn get_program_binary(&self, program: u32) -> (u32, Vec<u8>) {
+        let length = self.get_program_iv(program, gl::PROGRAM_BINARY_LENGTH);
+        let mut binary = vec![0u8; length.max(0) as usize];
+        let (mut written, mut format) = (0, 0);
+        gl_call!(gl::GetProgramBinary(
+            program,
+            binary.len() as i32,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr() as *mut c_void
+        ));
+        binary.truncate(written as usize);
+        (format, binary)
+    }
+    //
+    fn program_binary(
+        &self,
+        program: u32,
+        format: u32,
+        binary: &[u8],
+    ) -> Result<(), glcall::GlCallError> {
+        try_gl_call!(gl::ProgramBinary(
+            program,
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as i32
+        ))
+    }
+    //
+    fn gen_program_pipeline(&self) -> u32 {
+        let mut id = 0;
+        gl_call!(gl::GenProgramPipelines(1, &mut id));
+        id
+    }
+    //
+    fn bind_program_pipeline(&self, pipeline: u32) {
+        gl_call!(gl::BindProgramPipeline(pipeline));
+    }
+    //
+    fn use_program_stages(&self, pipeline: u32, stages: u32, program: u32) {
+        gl_call!(gl::UseProgramStages(pipeline, stages, program));
+    }
+    //
+    fn validate_program_pipeline(&self, pipeline: u32) {
+        gl_call!(gl::ValidateProgramPipeline(pipeline));
+    }
+    //
+    fn get_program_pipeline_iv(&self, pipeline: u32, pname: u32) -> i32 {
+        let mut result = 0;
+        gl_call!(gl::GetProgramPipelineiv(pipeline, pname, &mut result));
+        result
+    }
+    //
+    fn get_program_pipeline_info_log(&self, pipeline: u32) -> String {
+        let mut length =
+            self.get_program_pipeline_iv(pipeline, gl::INFO_LOG_LENGTH);
+        let mut message = vec![0u8; length.max(1) as usize];
+        gl_call!(gl::GetProgramPipelineInfoLog(
+            pipeline,
+            message.len() as i32,
+            &mut length,
+            message.as_mut_ptr() as *mut i8
+        ));
+        message.truncate(length as usize);
+        String::from_utf8_lossy(&message).into_owned()
+    }
+    //
+    fn delete_program_pipeline(&self, pipeline: u32) {
+        gl_call!(gl::DeleteProgramPipelines(1, &pipeline));
+    }
+    //
+    fn supports_spirv(&self) -> bool {
+        specialize_shader_fn().is_some() && gl::ShaderBinary::is_loaded()
+    }
+    //
+    fn shader_binary(&self, id: u32, format: u32, binary: &[u8]) {
+        gl_call!(gl::ShaderBinary(
+            1,
+            &id,
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as i32
+        ));
+    }
+    //
+    fn specialize_shader(
+        &self,
+        id: u32,
+        entry_point: &str,
+        constants: &[(u32, u32)],
+    ) {
+        let specialize =
+            specialize_shader_fn().expect("glSpecializeShader is not loaded");
+        let entry_point = CString::new(entry_point)
+            .expect("SpirvModule::compile rejects entry points with a NUL");
+        let (indices, values): (Vec<u32>, Vec<u32>) =
+            constants.iter().copied().unzip();
+        gl_call!(specialize(
+            id,
+            entry_point.as_ptr(),
+            constants.len() as u32,
+            indices.as_ptr(),
+            values.as_ptr()
+        ));
+    }
+    //
+    fn allocate_buffer(&self, target: u32, size: usize, usage: u32) {
+        gl_call!(gl::BufferData(
+            target,
+            size as isize,
+            std::ptr::null(),
+            usage
+        ));
+    }
+    //
+    fn copy_buffer_sub_data(
+        &self,
+        read_target: u32,
+        write_target: u32,
+        read_offset: usize,
+        write_offset: usize,
+        size: usize,
+    ) {
+        gl_call!(gl::CopyBufferSubData(
+            read_target,
+            write_target,
+            read_offset as isize,
+            write_offset as isize,
+            size as isize
+        ));
+    }
+    //
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        gl_call!(gl::DrawArrays(mode, first, count));
+    }
+    //
+    fn map_buffer_range(
+        &self,
+        target: u32,
+        offset: usize,
+        length: usize,
+        access: u32,
+    ) -> *mut c_void {
+        gl_call!(gl::MapBufferRange(
+            target,
+            offset as isize,
+            length as isize,
+            access
+        ))
+    }
+    //
+    fn unmap_buffer(&self, target: u32) -> bool {
+        gl_call!(gl::UnmapBuffer(target)) == gl::TRUE
+    }
+    //
+    fn buffer_storage(&self, target: u32, size: usize, flags: u32) -> bool {
+        let supported =
+            gl_version() >= (4, 4) || has_extension("GL_ARB_buffer_storage");
+        if !supported || !gl::BufferStorage::is_loaded() {
+            return false;
+        }
+        try_gl_call!(gl::BufferStorage(
+            target,
+            size as isize,
+            std::ptr::null(),
+            flags
+        ))
+        .is_ok()
+    }
+    //
+    fn fence_sync(&self) -> usize {
+        gl_call!(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0)) as usize
+    }
+    //
+    fn client_wait_sync(&self, sync: usize, flags: u32, timeout: u64) -> u32 {
+        gl_call!(gl::ClientWaitSync(
+            sync as gl::types::GLsync,
+            flags,
+            timeout
+        ))
+    }
+    //
+    fn delete_sync(&self, sync: usize) {
+        gl_call!(gl::DeleteSync(sync as gl::types::GLsync));
+    }
+    //
+    fn enable(&self, cap: u32) {
+        gl_call!(gl::Enable(cap));
+    }
+    //
+    fn disable(&self, cap: u32) {
+        gl_call!(gl::Disable(cap));
+    }
+    //
+    fn primitive_restart_index(&self, index: u32) {
+        gl_call!(gl::PrimitiveRestartIndex(index));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlCall {
+    pub name: &'static str,
+    pub args: String,
+}
+
+/// Records every call instead of issuing it, handing out sequential object
+/// names. Queries answer from `set_query_result`, defaulting to 1 so that
+/// compile and link status checks succeed. Framebuffer completeness is looked
+/// up under `gl::FRAMEBUFFER` and defaults to complete. `fail_on` makes a
+/// call raise an error the way `gl_call!` would.
+pub struct MockGl {
+    calls: RefCell<Vec<GlCall>>,
+    next_id: Cell<u32>,
+    query_results: RefCell<HashMap<u32, i32>>,
+    failures: RefCell<HashMap<&'static str, glcall::GlError>>,
+    viewport: Cell<[i32; 4]>,
+    /// Backs the pointers handed out by `map_buffer_range`, as words so
+    /// they are aligned for any element type up to 8 bytes.
+    mappings: RefCell<Vec<Vec<u64>>>,
+}
+
+impl MockGl {
+    pub fn new() -> Self {
+        Self {
+            calls: RefCell::new(Vec::new()),
+            next_id: Cell::new(1),
+            query_results: RefCell::new(HashMap::new()),
+            failures: RefCell::new(HashMap::new()),
+            viewport: Cell::new([0; 4]),
+            mappings: RefCell::new(Vec::new()),
+        }
+    }
+    //
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.calls.borrow().clone()
+    }
+    //
+    pub fn call_names(&self) -> Vec<&'static str> {
+        self.calls.borrow().iter().map(|call| call.name).collect()
+    }
+    //
+    pub fn clear_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+    //
+    pub fn set_query_result(&self, pname: u32, value: i32) {
+        self.query_results.borrow_mut().insert(pname, value);
+    }
+    //
+    /// Makes every later call named `name` raise `error`.
+    pub fn fail_on(&self, name: &'static str, error: glcall::GlError) {
+        self.failures.borrow_mut().insert(name, error);
+    }
+    //
+    /// Records the call and returns the error `fail_on` set up for it.
+    fn try_record(
+        &self,
+        name: &'static str,
+        args: String,
+    ) -> Result<(), glcall::GlCallError> {
+        self.calls.borrow_mut().push(GlCall {
+            name: name,
+            args: args,
+        });
+        match self.failures.borrow().get(name) {
+            Some(&error) => Err(glcall::GlCallError {
+                errors: vec![error],
+                file: file!(),
+                line: line!(),
+                call: name,
+            }),
+            None => Ok(()),
+        }
+    }
+    //
+    fn record(&self, name: &'static str, args: String) {
+        if let Err(error) = self.try_record(name, args) {
+            glcall::report(error);
+        }
+    }
+    //
+    /// Records the location, the number of array elements and the values.
+    fn record_uniform<T: std::fmt::Display>(
+        &self,
+        name: &'static str,
+        location: i32,
+        per_element: i32,
+        values: &[T],
+    ) {
+        let count = values.len() as i32 / per_element;
+        let mut args = format!("{}, {}", location, count);
+        for v in values {
+            write!(args, ", {}", v).unwrap();
+        }
+        self.record(name, args);
+    }
+    //
+    fn new_id(&self, name: &'static str) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.record(name, format!("-> {}", id));
+        id
+    }
+    //
+    fn query(&self, pname: u32) -> i32 {
+        *self
+            .query_results
+            .borrow()
+            .get(&pname)
+            .unwrap_or(&match pname {
+                gl::INFO_LOG_LENGTH
+                | gl::ACTIVE_UNIFORMS
+                | gl::ACTIVE_UNIFORM_BLOCKS
+                | gl::ACTIVE_ATTRIBUTES => 0,
+                _ => 1,
+            })
+    }
+}
+
+impl Default for MockGl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlBackend for MockGl {
+    fn gen_buffer(&self) -> u32 {
+        self.new_id("GenBuffers")
+    }
+    //
+    fn bind_buffer(&self, target: u32, id: u32) {
+        self.record("BindBuffer", format!("0x{:x}, {}", target, id));
+    }
+    //
+    fn buffer_data(&self, target: u32, data: &[u8], usage: u32) {
+        self.record(
+            "BufferData",
+            format!("0x{:x}, {} bytes, 0x{:x}", target, data.len(), usage),
+        );
+    }
+    //
+    fn buffer_sub_data(&self, target: u32, offset: usize, data: &[u8]) {
+        self.record(
+            "BufferSubData",
+            format!("0x{:x}, {}, {} bytes", target, offset, data.len()),
+        );
+    }
+    //
+    fn bind_buffer_base(&self, target: u32, index: u32, id: u32) {
+        self.record(
+            "BindBufferBase",
+            format!("0x{:x}, {}, {}", target, index, id),
+        );
+    }
+    //
+    fn delete_buffer(&self, id: u32) {
+        self.record("DeleteBuffers", format!("{}", id));
+    }
+    //
+    fn gen_vertex_array(&self) -> u32 {
+        self.new_id("GenVertexArrays")
+    }
+    //
+    fn bind_vertex_array(&self, id: u32) {
+        self.record("BindVertexArray", format!("{}", id));
+    }
+    //
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.record("EnableVertexAttribArray", format!("{}", index));
+    }
+    //
+    fn vertex_attrib_pointer(
+        &self,
+        index: u32,
+        count: i32,
+        el_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: usize,
+    ) {
+        self.record(
+            "VertexAttribPointer",
+            format!(
+                "{}, {}, 0x{:x}, {}, {}, {}",
+                index, count, el_type, normalized, stride, offset
+            ),
+        );
+    }
+    //
+    fn delete_vertex_array(&self, id: u32) {
+        self.record("DeleteVertexArrays", format!("{}", id));
+    }
+    //
+    fn create_shader(&self, shader_type: u32) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.record("CreateShader", format!("0x{:x} -> {}", shader_type, id));
+        id
+    }
+    //
+    fn shader_source(&self, id: u32, source: &str) {
+        self.record("ShaderSource", format!("{}, {} bytes", id, source.len()));
+    }
+    //
+    fn compile_shader(&self, id: u32) {
+        self.record("CompileShader", format!("{}", id));
+    }
+    //
+    fn get_shader_iv(&self, id: u32, pname: u32) -> i32 {
+        self.record("GetShaderiv", format!("{}, 0x{:x}", id, pname));
+        self.query(pname)
+    }
+    //
+    fn get_shader_info_log(&self, id: u32) -> String {
+        self.record("GetShaderInfoLog", format!("{}", id));
+        String::new()
+    }
+    //
+    fn delete_shader(&self, id: u32) {
+        self.record("DeleteShader", format!("{}", id));
+    }
+    //
+    fn create_program(&self) -> u32 {
+        self.new_id("CreateProgram")
+    }
+    //
+    fn attach_shader(&self, program: u32, shader: u32) {
+        self.record("AttachShader", format!("{}, {}", program, shader));
+    }
+    //
+    fn link_program(&self, program: u32) {
+        self.record("LinkProgram", format!("{}", program));
+    }
+    //
+    fn validate_program(&self, program: u32) {
+        self.record("ValidateProgram", format!("{}", program));
+    }
+    //
+    fn use_program(&self, program: u32) {
+        self.record("UseProgram", format!("{}", program));
+    }
+    //
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32 {
+        let location = self.next_id.get() as i32;
+        self.next_id.set(location as u32 + 1);
+        self.record(
+            "GetUniformLocation",
+            format!(
+                "{}, {:?} -> {}",
+                program,
+                name.trim_end_matches('\0'),
+                location
+            ),
+        );
+        location
+    }
+    //
+    fn uniform_fv(&self, location: i32, components: i32, values: &[f32]) {
+        let name = ["Uniform1fv", "Uniform2fv", "Uniform3fv", "Uniform4fv"]
+            [components as usize - 1];
+        self.record_uniform(name, location, components, values);
+    }
+    //
+    fn uniform_iv(&self, location: i32, components: i32, values: &[i32]) {
+        let name = ["Uniform1iv", "Uniform2iv", "Uniform3iv", "Uniform4iv"]
+            [components as usize - 1];
+        self.record_uniform(name, location, components, values);
+    }
+    //
+    fn uniform_uiv(&self, location: i32, components: i32, values: &[u32]) {
+        let name = ["Uniform1uiv", "Uniform2uiv", "Uniform3uiv", "Uniform4uiv"]
+            [components as usize - 1];
+        self.record_uniform(name, location, components, values);
+    }
+    //
+    fn uniform_matrix_fv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f32],
+    ) {
+        let name = [
+            [
+                "UniformMatrix2fv",
+                "UniformMatrix2x3fv",
+                "UniformMatrix2x4fv",
+            ],
+            [
+                "UniformMatrix3x2fv",
+                "UniformMatrix3fv",
+                "UniformMatrix3x4fv",
+            ],
+            [
+                "UniformMatrix4x2fv",
+                "UniformMatrix4x3fv",
+                "UniformMatrix4fv",
+            ],
+        ][columns as usize - 2][rows as usize - 2];
+        self.record_uniform(name, location, columns * rows, values);
+    }
+    //
+    fn uniform_dv(&self, location: i32, components: i32, values: &[f64]) {
+        let name = ["Uniform1dv", "Uniform2dv", "Uniform3dv", "Uniform4dv"]
+            [components as usize - 1];
+        self.record_uniform(name, location, components, values);
+    }
+    //
+    fn uniform_matrix_dv(
+        &self,
+        location: i32,
+        columns: i32,
+        rows: i32,
+        values: &[f64],
+    ) {
+        let name = [
+            [
+                "UniformMatrix2dv",
+                "UniformMatrix2x3dv",
+                "UniformMatrix2x4dv",
+            ],
+            [
+                "UniformMatrix3x2dv",
+                "UniformMatrix3dv",
+                "UniformMatrix3x4dv",
+            ],
+            [
+                "UniformMatrix4x2dv",
+                "UniformMatrix4x3dv",
+                "UniformMatrix4dv",
+            ],
+        ][columns as usize - 2][rows as usize - 2];
+        self.record_uniform(name, location, columns * rows, values);
+    }
+    //
+    fn delete_program(&self, program: u32) {
+        self.record("DeleteProgram", format!("{}", program));
+    }
+    //
+    fn gen_texture(&self) -> u32 {
+        self.new_id("GenTextures")
+    }
+    //
+    fn active_texture(&self, unit: u32) {
+        self.record("ActiveTexture", format!("0x{:x}", unit));
+    }
+    //
+    fn bind_texture(&self, target: u32, id: u32) {
+        self.record("BindTexture", format!("0x{:x}, {}", target, id));
+    }
+    //
+    fn tex_parameter_i(&self, target: u32, pname: u32, param: i32) {
+        self.record(
+            "TexParameteri",
+            format!("0x{:x}, 0x{:x}, 0x{:x}", target, pname, param),
+        );
+    }
+    //
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        data: Option<&[u8]>,
+    ) {
+        self.record(
+            "TexImage2D",
+            format!(
+                "0x{:x}, 0x{:x}, {}x{}, 0x{:x}, 0x{:x}, {} bytes",
+                target,
+                internal_format,
+                width,
+                height,
+                format,
+                data_type,
+                data.map_or(0, |d| d.len())
+            ),
+        );
+    }
+    //
+    fn delete_texture(&self, id: u32) {
+        self.record("DeleteTextures", format!("{}", id));
+    }
+    //
+    fn draw_elements(&self, mode: u32, count: i32, index_type: u32) {
+        self.record(
+            "DrawElements",
+            format!("0x{:x}, {}, 0x{:x}", mode, count, index_type),
+        );
+    }
+    //
+    fn clear(&self, mask: u32) {
+        self.record("Clear", format!("0x{:x}", mask));
+    }
+    //
+    fn gen_framebuffer(&self) -> u32 {
+        self.new_id("GenFramebuffers")
+    }
+    //
+    fn bind_framebuffer(&self, target: u32, id: u32) {
+        self.record("BindFramebuffer", format!("0x{:x}, {}", target, id));
+    }
+    //
+    fn framebuffer_texture_2d(&self, attachment: u32, texture: u32) {
+        self.record(
+            "FramebufferTexture2D",
+            format!("0x{:x}, {}", attachment, texture),
+        );
+    }
+    //
+    fn framebuffer_renderbuffer(&self, attachment: u32, renderbuffer: u32) {
+        self.record(
+            "FramebufferRenderbuffer",
+            format!("0x{:x}, {}", attachment, renderbuffer),
+        );
+    }
+    //
+    fn draw_buffers(&self, attachments: &[u32]) {
+        self.record("DrawBuffers", format!("{:x?}", attachments));
+    }
+    //
+    fn check_framebuffer_status(&self) -> u32 {
+        self.record("CheckFramebufferStatus", String::new());
+        *self
+            .query_results
+            .borrow()
+            .get(&gl::FRAMEBUFFER)
+            .unwrap_or(&(gl::FRAMEBUFFER_COMPLETE as i32)) as u32
+    }
+    //
+    fn delete_framebuffer(&self, id: u32) {
+        self.record("DeleteFramebuffers", format!("{}", id));
+    }
+    //
+    fn gen_renderbuffer(&self) -> u32 {
+        self.new_id("GenRenderbuffers")
+    }
+    //
+    fn bind_renderbuffer(&self, id: u32) {
+        self.record("BindRenderbuffer", format!("{}", id));
+    }
+    //
+    fn renderbuffer_storage(
+        &self,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    ) {
+        self.record(
+            "RenderbufferStorage",
+            format!("0x{:x}, {}x{}", internal_format, width, height),
+        );
+    }
+    //
+    fn delete_renderbuffer(&self, id: u32) {
+        self.record("DeleteRenderbuffers", format!("{}", id));
+    }
+    //
+    fn get_viewport(&self) -> [i32; 4] {
+        self.record("GetIntegerv", "VIEWPORT".to_owned());
+        self.viewport.get()
+    }
+    //
+    fn viewport(&self, viewport: [i32; 4]) {
+        self.record("Viewport", format!("{:?}", viewport));
+        self.viewport.set(viewport);
+    }
+    //
+    fn read_buffer(&self, mode: u32) {
+        self.record("ReadBuffer", format!("0x{:x}", mode));
+    }
+    //
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        pixels: &mut [u8],
+    ) {
+        self.record(
+            "ReadPixels",
+            format!("{}, {}, {}x{}", x, y, width, height),
+        );
+        for p in pixels.iter_mut() {
+            *p = 0;
+        }
+    }
+    //
+    fn get_integer(&self, pname: u32) -> i32 {
+        self.record("GetIntegerv", format!("0x{:x}", pname));
+        *self
+            .query_results
+            .borrow()
+            .get(&pname)
+            .unwrap_or(&match pname {
+                // The minimum GL 4.5 requires
+                gl::MAX_UNIFORM_BUFFER_BINDINGS => 36,
+                _ => 0,
+            })
+    }
+    //
+    fn get_program_iv(&self, program: u32, pname: u32) -> i32 {
+        self.record("GetProgramiv", format!("{}, 0x{:x}", program, pname));
+        self.query(pname)
+    }
+    //
+    fn get_program_info_log(&self, program: u32) -> String {
+        self.record("GetProgramInfoLog", format!("{}", program));
+        String::new()
+    }
+    //
+    fn dispatch_compute(&self, x: u32, y: u32, z: u32) {
+        self.record("DispatchCompute", format!("{}, {}, {}", x, y, z));
+    }
+    //
+    fn memory_barrier(&self, barriers: u32) {
+        self.record("MemoryBarrier", format!("0x{:x}", barriers));
+    }
+    //
+    fn get_active_uniform(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> (String, i32, u32) {
+        self.record("GetActiveUniform", format!("{}, {}", program, index));
+        (format!("uniform{}", index), 1, gl::FLOAT_VEC4)
+    }
+    //
+    fn get_active_uniform_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32 {
+        self.record(
+            "GetActiveUniformsiv",
+            format!("{}, {}, 0x{:x}", program, index, pname),
+        );
+        match pname {
+            gl::UNIFORM_BLOCK_INDEX => -1,
+            _ => self.query(pname),
+        }
+    }
+    //
+    fn get_active_uniform_block_name(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> String {
+        self.record(
+            "GetActiveUniformBlockName",
+            format!("{}, {}", program, index),
+        );
+        format!("block{}", index)
+    }
+    //
+    fn get_active_uniform_block_iv(
+        &self,
+        program: u32,
+        index: u32,
+        pname: u32,
+    ) -> i32 {
+        self.record(
+            "GetActiveUniformBlockiv",
+            format!("{}, {}, 0x{:x}", program, index, pname),
+        );
+        self.query(pname)
+    }
+    //
+    fn get_active_attrib(
+        &self,
+        program: u32,
+        index: u32,
+    ) -> (String, i32, u32) {
+        self.record("GetActiveAttrib", format!("{}, {}", program, index));
+        (format!("attrib{}", index), 1, gl::FLOAT_VEC4)
+    }
+    //
+    fn get_attrib_location(&self, program: u32, name: &str) -> i32 {
+        self.record("GetAttribLocation", format!("{}, {:?}", program, name));
+        0
+    }
+    //
+    fn uniform_block_binding(&self, program: u32, index: u32, binding: u32) {
+        self.record(
+            "UniformBlockBinding",
+            format!("{}, {}, {}", program, index, binding),
+        );
+    }
+    //
+    fn get_string(&self, name: u32) -> String {
+        self.record("GetString", format!("0x{:x}", name));
+        "MockGl".to_owned()
+    }
+    //
+    /// Formats 1 to `GL_NUM_PROGRAM_BINARY_FORMATS`, none by default.
+    fn program_binary_formats(&self) -> Vec<u32> {
+        let count = self.get_integer(gl::NUM_PROGRAM_BINARY_FORMATS);
+        (1..=count.max(0) as u32).collect()
+    }
+    //
+    fn program_parameter_i(&self, program: u32, pname: u32, value: i32) {
+        self.record(
+            "ProgramParameteri",
+            format!("{}, 0x{:x}, {}", program, pname, value),
+        );
+    }
+    //
+    fn get_program_binary(&self, program: u32) -> (u32, Vec<u8>) {
+        self.record("GetProgramBinary", format!("{}", program));
+        (0, Vec::new())
+    }
+    //
+    fn program_binary(
+        &self,
+        program: u32,
+        format: u32,
+        binary: &[u8],
+    ) -> Result<(), glcall::GlCallError> {
+        self.try_record(
+            "ProgramBinary",
+            format!("{}, 0x{:x}, {} bytes", program, format, binary.len()),
+        )
+    }
+    //
+    fn gen_program_pipeline(&self) -> u32 {
+        self.new_id("GenProgramPipelines")
+    }
+    //
+    fn bind_program_pipeline(&self, pipeline: u32) {
+        self.record("BindProgramPipeline", format!("{}", pipeline));
+    }
+    //
+    fn use_program_stages(&self, pipeline: u32, stages: u32, program: u32) {
+        self.record(
+            "UseProgramStages",
+            format!("{}, 0x{:x}, {}", pipeline, stages, program),
+        );
+    }
+    //
+    fn validate_program_pipeline(&self, pipeline: u32) {
+        self.record("ValidateProgramPipeline", format!("{}", pipeline));
+    }
+    //
+    fn get_program_pipeline_iv(&self, pipeline: u32, pname: u32) -> i32 {
+        self.record(
+            "GetProgramPipelineiv",
+            format!("{}, 0x{:x}", pipeline, pname),
+        );
+        self.query(pname)
+    }
+    //
+    fn get_program_pipeline_info_log(&self, pipeline: u32) -> String {
+        self.record("GetProgramPipelineInfoLog", format!("{}", pipeline));
+        String::new()
+    }
+    //
+    fn delete_program_pipeline(&self, pipeline: u32) {
+        self.record("DeleteProgramPipelines", format!("{}", pipeline));
+    }
+    //
+    fn supports_spirv(&self) -> bool {
+        true
+    }
+    //
+    fn shader_binary(&self, id: u32, format: u32, binary: &[u8]) {
+        self.record(
+            "ShaderBinary",
+            format!("{}, 0x{:x}, {} bytes", id, format, binary.len()),
+        );
+    }
+    //
+    fn specialize_shader(
+        &self,
+        id: u32,
+        entry_point: &str,
+        constants: &[(u32, u32)],
+    ) {
+        let mut args = format!("{}, {}", id, entry_point);
+        for (index, value) in constants {
+            write!(args, ", {}=0x{:x}", index, value).unwrap();
+        }
+        self.record("SpecializeShader", args);
+    }
+    //
+    fn allocate_buffer(&self, target: u32, size: usize, usage: u32) {
+        self.record(
+            "BufferData",
+            format!("0x{:x}, {} bytes, null, 0x{:x}", target, size, usage),
+        );
+    }
+    //
+    fn copy_buffer_sub_data(
+        &self,
+        read_target: u32,
+        write_target: u32,
+        read_offset: usize,
+        write_offset: usize,
+        size: usize,
+    ) {
+        self.record(
+            "CopyBufferSubData",
+            format!(
+                "0x{:x}, 0x{:x}, {}, {}, {}",
+                read_target, write_target, read_offset, write_offset, size
+            ),
+        );
+    }
+    //
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        self.record(
+            "DrawArrays",
+            format!("0x{:x}, {}, {}", mode, first, count),
+        );
+    }
+    //
+    fn map_buffer_range(
+        &self,
+        target: u32,
+        offset: usize,
+        length: usize,
+        access: u32,
+    ) -> *mut c_void {
+        let args =
+            format!("0x{:x}, {}, {}, 0x{:x}", target, offset, length, access);
+        if let Err(error) = self.try_record("MapBufferRange", args) {
+            glcall::report(error);
+            return std::ptr::null_mut();
+        }
+        let mut mappings = self.mappings.borrow_mut();
+        mappings.push(vec![0; length / 8 + 1]);
+        mappings.last_mut().unwrap().as_mut_ptr() as *mut c_void
+    }
+    //
+    fn unmap_buffer(&self, target: u32) -> bool {
+        self.record("UnmapBuffer", format!("0x{:x}", target));
+        true
+    }
+    //
+    fn buffer_storage(&self, target: u32, size: usize, flags: u32) -> bool {
+        self.try_record(
+            "BufferStorage",
+            format!("0x{:x}, {} bytes, 0x{:x}", target, size, flags),
+        )
+        .is_ok()
+    }
+    //
+    fn fence_sync(&self) -> usize {
+        self.new_id("FenceSync") as usize
+    }
+    //
+    fn client_wait_sync(&self, sync: usize, flags: u32, timeout: u64) -> u32 {
+        self.record(
+            "ClientWaitSync",
+            format!("{}, 0x{:x}, {}", sync, flags, timeout),
+        );
+        gl::ALREADY_SIGNALED
+    }
+    //
+    fn delete_sync(&self, sync: usize) {
+        self.record("DeleteSync", format!("{}", sync));
+    }
+    //
+    fn enable(&self, cap: u32) {
+        self.record("Enable", format!("0x{:x}", cap));
+    }
+    //
+    fn disable(&self, cap: u32) {
+        self.record("Disable", format!("0x{:x}", cap));
+    }
+    //
+    fn primitive_restart_index(&self, index: u32) {
+        self.record("PrimitiveRestartIndex", format!("{}", index));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub const COLOR_SHADER: &str = "res/shaders/unit/color.shader";
+
+    /// Makes a fresh `MockGl` current. Every test runs on its own thread,
+    /// so it stays current for the rest of the test.
+    pub fn install_mock() -> Rc<MockGl> {
+        let mock = Rc::new(MockGl::new());
+        set_current(mock.clone());
+        mock
+    }
+
+    /// The recorded calls as `Name(args)`, to compare against a literal log.
+    pub fn call_log(mock: &MockGl) -> Vec<String> {
+        mock.calls()
+            .iter()
+            .map(|call| format!("{}({})", call.name, call.args))
+            .collect()
+    }
+}
@@ -1,32 +1,234 @@
 use gl;
 
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Makes the call and returns its result together with the errors it raised.
+macro_rules! checked_gl_call {
+    ($x:expr) => {{
+        let polling = glcall::polling_enabled();
+        if polling {
+            glcall::gl_clear_error();
+        } else {
+            glcall::take_error_reported();
+        }
+        let result = unsafe { $x };
+        let errors = if polling || glcall::take_error_reported() {
+            glcall::gl_get_errors()
+        } else {
+            Vec::new()
+        };
+        let error = if errors.is_empty() {
+            None
+        } else {
+            Some(glcall::GlCallError {
+                errors: errors,
+                file: file!(),
+                line: line!(),
+                call: stringify!($x),
+            })
+        };
+        (result, error)
+    }};
+}
+
+// #[macro_export]
+macro_rules! try_gl_call {
+    ($x:expr) => {{
+        match checked_gl_call!($x) {
+            (result, None) => Ok(result),
+            (_, Some(e)) => Err(e),
+        }
+    }};
+}
+
 // #[macro_export]
 macro_rules! gl_call {
     ($x:expr) => {{
-        glcall::gl_clear_error();
-        let result = unsafe { $x };
-        if !(glcall::gl_log_call()) {
-            panic!("OpenGL error at\n{}\n", stringify!($x));
+        let (result, error) = checked_gl_call!($x);
+        if let Some(e) = error {
+            glcall::report(e);
         }
         result
     }};
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlError {
+    InvalidEnum,
+    InvalidValue,
+    InvalidOperation,
+    StackOverflow,
+    StackUnderflow,
+    OutOfMemory,
+    InvalidFramebufferOperation,
+    ContextLost,
+    Unknown(u32),
+}
+
+impl GlError {
+    pub fn code(&self) -> u32 {
+        match *self {
+            GlError::InvalidEnum => gl::INVALID_ENUM,
+            GlError::InvalidValue => gl::INVALID_VALUE,
+            GlError::InvalidOperation => gl::INVALID_OPERATION,
+            GlError::StackOverflow => gl::STACK_OVERFLOW,
+            GlError::StackUnderflow => gl::STACK_UNDERFLOW,
+            GlError::OutOfMemory => gl::OUT_OF_MEMORY,
+            GlError::InvalidFramebufferOperation => {
+                gl::INVALID_FRAMEBUFFER_OPERATION
+            }
+            GlError::ContextLost => gl::CONTEXT_LOST,
+            GlError::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<u32> for GlError {
+    fn from(code: u32) -> Self {
+        match code {
+            gl::INVALID_ENUM => GlError::InvalidEnum,
+            gl::INVALID_VALUE => GlError::InvalidValue,
+            gl::INVALID_OPERATION => GlError::InvalidOperation,
+            gl::STACK_OVERFLOW => GlError::StackOverflow,
+            gl::STACK_UNDERFLOW => GlError::StackUnderflow,
+            gl::OUT_OF_MEMORY => GlError::OutOfMemory,
+            gl::INVALID_FRAMEBUFFER_OPERATION => {
+                GlError::InvalidFramebufferOperation
+            }
+            gl::CONTEXT_LOST => GlError::ContextLost,
+            code => GlError::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for GlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            GlError::InvalidEnum => "GL_INVALID_ENUM",
+            GlError::InvalidValue => "GL_INVALID_VALUE",
+            GlError::InvalidOperation => "GL_INVALID_OPERATION",
+            GlError::StackOverflow => "GL_STACK_OVERFLOW",
+            GlError::StackUnderflow => "GL_STACK_UNDERFLOW",
+            GlError::OutOfMemory => "GL_OUT_OF_MEMORY",
+            GlError::InvalidFramebufferOperation => {
+                "GL_INVALID_FRAMEBUFFER_OPERATION"
+            }
+            GlError::ContextLost => "GL_CONTEXT_LOST",
+            GlError::Unknown(_) => "unknown error",
+        };
+        write!(f, "{} (0x{:x})", name, self.code())
+    }
+}
+
+impl Error for GlError {}
+
+/// Every error raised by a single call, together with where it was made.
+#[derive(Debug, Clone)]
+pub struct GlCallError {
+    pub errors: Vec<GlError>,
+    pub file: &'static str,
+    pub line: u32,
+    pub call: &'static str,
+}
+
+impl fmt::Display for GlCallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "OpenGL error at {}:{}", self.file, self.line)?;
+        writeln!(f, "{}", self.call)?;
+        for error in &self.errors {
+            writeln!(f, "    {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for GlCallError {}
+
+thread_local! {
+    // The first error raised inside the innermost `capture_errors`, the
+    // outer `None` meaning there is no capture.
+    static CAPTURED: RefCell<Option<Option<GlCallError>>> = RefCell::new(None);
+}
+
+/// Hands a failed `gl_call!` to the enclosing `capture_errors`, or panics
+/// outside of one.
+pub fn report(error: GlCallError) {
+    let unhandled =
+        CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(first) => {
+                first.get_or_insert(error);
+                None
+            }
+            None => Some(error),
+        });
+    if let Some(error) = unhandled {
+        panic!("{}", error);
+    }
+}
+
+/// Runs `f` with the `gl_call!`s in it reporting their first error here
+/// instead of panicking. The calls after a failed one still run, GL ignores
+/// the failed call itself.
+pub fn capture_errors<T>(f: impl FnOnce() -> T) -> (T, Option<GlCallError>) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(None)));
+    let value = f();
+    let error = CAPTURED.with(|captured| captured.replace(outer));
+    (value, error.flatten())
+}
+
+/// `capture_errors` for a value that frees its GL objects when dropped,
+/// which it is on error.
+pub fn try_create<T>(f: impl FnOnce() -> T) -> Result<T, GlCallError> {
+    match capture_errors(f) {
+        (value, None) => Ok(value),
+        (_, Some(error)) => Err(error),
+    }
+}
+
+// Cleared while a debug message callback is installed, since the driver
+// then reports errors itself and `glGetError` only needs polling after a
+// call it reported one for.
+static POLL_ERRORS: AtomicBool = AtomicBool::new(true);
+
+// Set by the debug message callback when the driver reports an error.
+static ERROR_REPORTED: AtomicBool = AtomicBool::new(false);
+
+pub fn polling_enabled() -> bool {
+    POLL_ERRORS.load(Ordering::Relaxed)
+}
+
+pub fn set_polling_enabled(enabled: bool) {
+    POLL_ERRORS.store(enabled, Ordering::Relaxed);
+}
+
+/// Called from the debug message callback for messages of type
+/// `GL_DEBUG_TYPE_ERROR`. The callback is synchronous, so the error belongs
+/// to the call in progress.
+pub fn set_error_reported() {
+    ERROR_REPORTED.store(true, Ordering::Relaxed);
+}
+
+pub fn take_error_reported() -> bool {
+    ERROR_REPORTED.swap(false, Ordering::Relaxed)
+}
+
 pub fn gl_clear_error() {
     unsafe { while gl::GetError() != gl::NO_ERROR {} }
 }
 
-pub fn gl_log_call() -> bool {
-    unsafe {
-        let mut error;
-        let mut result = true;
-        while {
-            error = gl::GetError();
-            error != gl::NO_ERROR
-        } {
-            println!("OpenGL_Error (0x{:x})", error);
-            result = false;
+pub fn gl_get_errors() -> Vec<GlError> {
+    let mut errors = Vec::new();
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
         }
-        result
+        errors.push(GlError::from(error));
     }
-}
\ No newline at end of file
+    errors
+}
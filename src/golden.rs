@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use super::{headless, HeadlessContext, TestMenu};
+
+pub struct GoldenOptions {
+    pub dir: PathBuf,
+    pub frames: u32,
+    pub delta_time: f32,
+    /// Largest per-channel difference still counted as a match.
+    pub tolerance: u8,
+    /// Overwrite the goldens with the current output instead of comparing.
+    pub update: bool,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("res/golden"),
+            frames: 10,
+            delta_time: 1.0 / 60.0,
+            tolerance: 2,
+            update: false,
+        }
+    }
+}
+
+pub enum GoldenResult {
+    Passed,
+    Updated,
+    Missing(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Failed {
+        mismatched: usize,
+        diff_path: PathBuf,
+    },
+    /// The golden, or the diff and actual image of a failure, could not be
+    /// written.
+    SaveFailed {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+}
+
+impl GoldenResult {
+    pub fn is_ok(&self) -> bool {
+        match self {
+            GoldenResult::Passed | GoldenResult::Updated => true,
+            _ => false,
+        }
+    }
+}
+
+fn golden_file_name(test_name: &str) -> String {
+    test_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Counts the pixels differing by more than `tolerance` in any channel and
+/// builds an image with those pixels in red over a dimmed copy of `expected`.
+fn diff_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    tolerance: u8,
+) -> (usize, RgbaImage) {
+    let tolerance = tolerance as i16;
+    let mut mismatched = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    //
+    for ((e, a), d) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let differs =
+            e.0.iter()
+                .zip(a.0.iter())
+                .any(|(&e, &a)| (e as i16 - a as i16).abs() > tolerance);
+        //
+        *d = if differs {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let gray = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+            Rgba([gray, gray, gray, 255])
+        };
+    }
+    //
+    (mismatched, diff)
+}
+
+fn check_golden(
+    actual: &RgbaImage,
+    golden_path: &Path,
+    tolerance: u8,
+) -> GoldenResult {
+    let expected = match image::open(golden_path) {
+        Ok(img) => img.to_rgba(),
+        Err(_) => return GoldenResult::Missing(golden_path.to_owned()),
+    };
+    //
+    if expected.dimensions() != actual.dimensions() {
+        return GoldenResult::SizeMismatch {
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        };
+    }
+    //
+    let (mismatched, diff) = diff_images(&expected, actual, tolerance);
+    if mismatched == 0 {
+        return GoldenResult::Passed;
+    }
+    //
+    let diff_path = golden_path.with_extension("diff.png");
+    let actual_path = golden_path.with_extension("actual.png");
+    if let Err(error) = diff.save(&diff_path) {
+        return GoldenResult::SaveFailed {
+            path: diff_path,
+            error: error,
+        };
+    }
+    if let Err(error) = actual.save(&actual_path) {
+        return GoldenResult::SaveFailed {
+            path: actual_path,
+            error: error,
+        };
+    }
+    //
+    GoldenResult::Failed {
+        mismatched: mismatched,
+        diff_path: diff_path,
+    }
+}
+
+/// Renders every registered test and compares the last frame against
+/// `<dir>/<test name>.png`. Returns whether all of them passed.
+pub fn run_all(
+    context: &HeadlessContext,
+    test_menu: &mut TestMenu,
+    options: &GoldenOptions,
+) -> bool {
+    if options.update {
+        if let Err(e) = std::fs::create_dir_all(&options.dir) {
+            println!("Failed to create {}: {}", options.dir.display(), e);
+            return false;
+        }
+    }
+    //
+    let names: Vec<String> = test_menu
+        .test_names()
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    let mut all_ok = true;
+    //
+    for name in &names {
+        let run = headless::run_test(
+            test_menu,
+            name,
+            options.frames,
+            options.delta_time,
+        );
+        if let Err(e) = run {
+            println!("[FAIL] {}: {}", name, e);
+            all_ok = false;
+            continue;
+        }
+        //
+        let actual = context.read_pixels();
+        let golden_path =
+            options.dir.join(format!("{}.png", golden_file_name(name)));
+        //
+        let result = if options.update {
+            match actual.save(&golden_path) {
+                Ok(()) => GoldenResult::Updated,
+                Err(error) => GoldenResult::SaveFailed {
+                    path: golden_path.clone(),
+                    error: error,
+                },
+            }
+        } else {
+            check_golden(&actual, &golden_path, options.tolerance)
+        };
+        //
+        match &result {
+            GoldenResult::Passed => println!("[ ok ] {}", name),
+            GoldenResult::Updated => {
+                println!("[updt] {} -> {}", name, golden_path.display())
+            }
+            GoldenResult::Missing(path) => println!(
+                "[FAIL] {}: no golden at {}, run with --update-golden",
+                name,
+                path.display()
+            ),
+            GoldenResult::SizeMismatch { expected, actual } => println!(
+                "[FAIL] {}: golden is {}x{}, rendered {}x{}",
+                name, expected.0, expected.1, actual.0, actual.1
+            ),
+            GoldenResult::Failed {
+                mismatched,
+                diff_path,
+            } => println!(
+                "[FAIL] {}: {} pixels differ, see {}",
+                name,
+                mismatched,
+                diff_path.display()
+            ),
+            GoldenResult::SaveFailed { path, error } => println!(
+                "[FAIL] {}: could not save {}: {}",
+                name,
+                path.display(),
+                error
+            ),
+        }
+        //
+        all_ok &= result.is_ok();
+    }
+    //
+    all_ok
+}
@@ -0,0 +1,196 @@
+use super::{gl_backend, glcall};
+
+use std::ffi::c_void;
+
+use image::RgbaImage;
+
+use khronos_egl as egl;
+
+use super::{FrameClock, Framebuffer, OGLTest, Renderer, TestMenu};
+
+// EGL_MESA_platform_surfaceless
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// A windowless OpenGL 3.3 core context created through EGL, rendering into
+/// an offscreen framebuffer. Prefers Mesa's surfaceless platform so that
+/// llvmpipe works without a display server, and falls back to a 1x1 pbuffer
+/// when surfaceless contexts are not supported.
+pub struct HeadlessContext {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    context: egl::Context,
+    surface: Option<egl::Surface>,
+    framebuffer: Option<Framebuffer>,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn get_display(
+    egl: &egl::DynamicInstance<egl::EGL1_4>,
+) -> Result<egl::Display, String> {
+    if let Some(egl15) = egl.upcast::<egl::EGL1_5>() {
+        let display = unsafe {
+            egl15.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+        };
+        if let Ok(display) = display {
+            return Ok(display);
+        }
+    }
+    //
+    unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }
+        .ok_or_else(|| "No EGL display available".to_owned())
+}
+
+impl HeadlessContext {
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        let egl =
+            unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+                .map_err(|e| format!("Failed to load libEGL: {}", e))?;
+        //
+        let display = get_display(&egl)?;
+        egl.initialize(display)
+            .map_err(|e| format!("eglInitialize failed: {}", e))?;
+        egl.bind_api(egl::OPENGL_API)
+            .map_err(|e| format!("eglBindAPI failed: {}", e))?;
+        //
+        let config = egl
+            .choose_first_config(
+                display,
+                &[
+                    egl::SURFACE_TYPE,
+                    egl::PBUFFER_BIT,
+                    egl::RENDERABLE_TYPE,
+                    egl::OPENGL_BIT,
+                    egl::NONE,
+                ],
+            )
+            .map_err(|e| format!("eglChooseConfig failed: {}", e))?
+            .ok_or_else(|| "No EGL config supports OpenGL".to_owned())?;
+        //
+        // 4.1 for separate shader objects, or 3.3 where that is the most
+        // the driver offers
+        let create_context = |major, minor| {
+            egl.create_context(
+                display,
+                config,
+                None,
+                &[
+                    egl::CONTEXT_MAJOR_VERSION,
+                    major,
+                    egl::CONTEXT_MINOR_VERSION,
+                    minor,
+                    egl::CONTEXT_OPENGL_PROFILE_MASK,
+                    egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                    egl::NONE,
+                ],
+            )
+        };
+        let context = create_context(4, 1)
+            .or_else(|_| create_context(3, 3))
+            .map_err(|e| format!("eglCreateContext failed: {}", e))?;
+        //
+        let surfaceless = egl
+            .query_string(Some(display), egl::EXTENSIONS)
+            .map(|ext| {
+                ext.to_string_lossy()
+                    .split(' ')
+                    .any(|e| e == "EGL_KHR_surfaceless_context")
+            })
+            .unwrap_or(false);
+        //
+        let surface = if surfaceless {
+            None
+        } else {
+            Some(
+                egl.create_pbuffer_surface(
+                    display,
+                    config,
+                    &[egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE],
+                )
+                .map_err(|e| {
+                    format!("eglCreatePbufferSurface failed: {}", e)
+                })?,
+            )
+        };
+        //
+        egl.make_current(display, surface, surface, Some(context))
+            .map_err(|e| format!("eglMakeCurrent failed: {}", e))?;
+        //
+        gl_backend::load_with(|s| {
+            egl.get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const c_void)
+        });
+        //
+        let framebuffer = Framebuffer::new(width, height, &[gl::RGBA8], None)
+            .map_err(|e| {
+            format!("Offscreen framebuffer incomplete: {}", e)
+        })?;
+        framebuffer.bind();
+        //
+        Ok(Self {
+            egl: egl,
+            display: display,
+            context: context,
+            surface: surface,
+            framebuffer: Some(framebuffer),
+            width: width,
+            height: height,
+        })
+    }
+}
+
+impl HeadlessContext {
+    /// Reads back the offscreen framebuffer, top row first.
+    pub fn read_pixels(&self) -> RgbaImage {
+        self.framebuffer.as_ref().unwrap().read_pixels(0)
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        // The framebuffer has to go while the context is still current.
+        self.framebuffer.take();
+        //
+        self.egl.make_current(self.display, None, None, None).ok();
+        if let Some(surface) = self.surface {
+            self.egl.destroy_surface(self.display, surface).ok();
+        }
+        self.egl.destroy_context(self.display, self.context).ok();
+        self.egl.terminate(self.display).ok();
+    }
+}
+
+/// Runs the registered test called `name` for `frames` frames on a fixed
+/// clock of `delta_time` seconds per frame.
+pub fn run_test(
+    test_menu: &mut TestMenu,
+    name: &str,
+    frames: u32,
+    delta_time: f32,
+) -> Result<(), String> {
+    if !test_menu.select_test(name) {
+        return Err(format!(
+            "No test named '{}', registered tests are: {}",
+            name,
+            test_menu.test_names().join(", ")
+        ));
+    }
+    //
+    test_menu.set_clock(FrameClock::fixed(delta_time));
+    let renderer = Renderer {};
+    //
+    for _ in 0..frames {
+        gl_call!(gl::ClearColor(0.0, 0.0, 0.0, 1.0));
+        renderer.clear();
+        //
+        test_menu.tick();
+        test_menu.on_render();
+    }
+    gl_call!(gl::Finish());
+    //
+    Ok(())
+}
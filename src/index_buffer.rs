@@ -1,53 +1,411 @@
-use super::glcall;
+use super::{gl_backend, glcall};
 
-use std::ffi::c_void;
+use super::{GlCallError, MapAccess, MappedRange, MappedRangeMut};
+
+use std::{convert::TryFrom, ops::Range};
+
+/// The type of the indices in an `IndexBuffer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexType {
+    U8,
+    U16,
+    U32,
+}
+
+impl IndexType {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            IndexType::U8 => gl::UNSIGNED_BYTE,
+            IndexType::U16 => gl::UNSIGNED_SHORT,
+            IndexType::U32 => gl::UNSIGNED_INT,
+        }
+    }
+    //
+    /// Bytes per index.
+    pub fn size(&self) -> usize {
+        match self {
+            IndexType::U8 => 1,
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        }
+    }
+    //
+    /// The largest index the type can hold.
+    pub fn max_index(&self) -> u32 {
+        match self {
+            IndexType::U8 => u8::MAX as u32,
+            IndexType::U16 => u16::MAX as u32,
+            IndexType::U32 => u32::MAX,
+        }
+    }
+    //
+    /// The smallest type that can hold `max_index`.
+    pub fn for_max_index(max_index: u32) -> Self {
+        [IndexType::U8, IndexType::U16]
+            .iter()
+            .copied()
+            .find(|t| max_index <= t.max_index())
+            .unwrap_or(IndexType::U32)
+    }
+}
+
+/// The primitives the indices of an `IndexBuffer` form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    Lines,
+    LineStrip,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Topology {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            Topology::Lines => gl::LINES,
+            Topology::LineStrip => gl::LINE_STRIP,
+            Topology::Triangles => gl::TRIANGLES,
+            Topology::TriangleStrip => gl::TRIANGLE_STRIP,
+            Topology::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+    //
+    /// Whether primitives share vertices, so several of them have to be
+    /// separated by primitive restart.
+    pub fn is_joinable(&self) -> bool {
+        match self {
+            Topology::LineStrip
+            | Topology::TriangleStrip
+            | Topology::TriangleFan => true,
+            Topology::Lines | Topology::Triangles => false,
+        }
+    }
+}
+
+/// An integer type GL can read indices as: `u8`, `u16` or `u32`.
+pub trait IndexElement: Copy + PartialEq + 'static {
+    const INDEX_TYPE: IndexType;
+    /// Truncates, so the index has to fit.
+    fn from_u32(index: u32) -> Self;
+}
+
+impl IndexElement for u8 {
+    const INDEX_TYPE: IndexType = IndexType::U8;
+    //
+    fn from_u32(index: u32) -> Self {
+        index as u8
+    }
+}
+
+impl IndexElement for u16 {
+    const INDEX_TYPE: IndexType = IndexType::U16;
+    //
+    fn from_u32(index: u32) -> Self {
+        index as u16
+    }
+}
+
+impl IndexElement for u32 {
+    const INDEX_TYPE: IndexType = IndexType::U32;
+    //
+    fn from_u32(index: u32) -> Self {
+        index
+    }
+}
 
 pub struct IndexBuffer {
     renderer_id: u32,
     pub count: i32,
+    index_type: IndexType,
+    topology: Topology,
+    /// Whether the largest value of the index type separates strips.
+    primitive_restart: bool,
 }
 
 impl IndexBuffer {
+    /// A triangle list.
+    pub fn new<I: IndexElement>(data: &[I]) -> Result<Self, GlCallError> {
+        Self::with_topology(data, Topology::Triangles)
+    }
+    //
+    pub fn with_topology<I: IndexElement>(
+        data: &[I],
+        topology: Topology,
+    ) -> Result<Self, GlCallError> {
+        let backend = gl_backend::current();
+        //
+        glcall::try_create(|| {
+            let ib = Self {
+                renderer_id: backend.gen_buffer(),
+                count: data.len() as i32,
+                index_type: I::INDEX_TYPE,
+                topology: topology,
+                primitive_restart: false,
+            };
+            ib.bind();
+            backend.buffer_data(
+                gl::ELEMENT_ARRAY_BUFFER,
+                gl_backend::as_bytes(data),
+                gl::STATIC_DRAW,
+            );
+            ib
+        })
+    }
+    //
+    /// A line list with one pair of indices per line.
+    pub fn lines<I: IndexElement>(
+        lines: &[[I; 2]],
+    ) -> Result<Self, GlCallError> {
+        let data: Vec<I> =
+            lines.iter().flat_map(|l| l.iter().copied()).collect();
+        Self::with_topology(&data, Topology::Lines)
+    }
+    //
+    /// Joins several strips or fans into one buffer, separated by the
+    /// primitive restart index, the largest value of `I`, so they are
+    /// drawn with a single call. That value cannot be used as an index.
+    /// Panics unless `topology` is a strip or fan topology.
+    pub fn joined<I: IndexElement>(
+        topology: Topology,
+        parts: &[&[I]],
+    ) -> Result<Self, GlCallError> {
+        assert!(
+            topology.is_joinable(),
+            "{:?} cannot be joined with primitive restart",
+            topology
+        );
+        let restart = I::from_u32(I::INDEX_TYPE.max_index());
+        let mut data = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            assert!(
+                !part.contains(&restart),
+                "{} is the primitive restart index",
+                I::INDEX_TYPE.max_index()
+            );
+            if i > 0 {
+                data.push(restart);
+            }
+            data.extend_from_slice(part);
+        }
+        //
+        let mut ib = Self::with_topology(&data, topology)?;
+        ib.primitive_restart = parts.len() > 1;
+        Ok(ib)
+    }
+    //
+    pub fn triangle_strips<I: IndexElement>(
+        strips: &[&[I]],
+    ) -> Result<Self, GlCallError> {
+        Self::joined(Topology::TriangleStrip, strips)
+    }
+    //
+    pub fn triangle_fans<I: IndexElement>(
+        fans: &[&[I]],
+    ) -> Result<Self, GlCallError> {
+        Self::joined(Topology::TriangleFan, fans)
+    }
+    //
+    pub fn line_strips<I: IndexElement>(
+        strips: &[&[I]],
+    ) -> Result<Self, GlCallError> {
+        Self::joined(Topology::LineStrip, strips)
+    }
+    //
+    /// Stores `indices` as the smallest type that holds the largest of
+    /// them. Most meshes have fewer than 65536 vertices and get by with
+    /// half the memory of `u32` indices.
+    pub fn compact(indices: &[u32]) -> Result<Self, GlCallError> {
+        let max_index = indices.iter().copied().max().unwrap_or(0);
+        match IndexType::for_max_index(max_index) {
+            IndexType::U8 => Self::new(&convert::<u8>(indices)),
+            IndexType::U16 => Self::new(&convert::<u16>(indices)),
+            IndexType::U32 => Self::new(indices),
+        }
+    }
+    //
     pub fn bind(&self) {
-        gl_call!(gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.renderer_id));
+        gl_backend::current()
+            .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.renderer_id);
     }
     pub fn unbind(&self) {
-        gl_call!(gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0));
+        gl_backend::current().bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+    }
+    //
+    pub fn index_type(&self) -> IndexType {
+        self.index_type
+    }
+    //
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+    //
+    /// The index that starts a new strip or fan, if the buffer uses one.
+    pub fn restart_index(&self) -> Option<u32> {
+        if self.primitive_restart {
+            Some(self.index_type.max_index())
+        } else {
+            None
+        }
+    }
+    //
+    /// Maps the indices in `range` for reading. The range has to lie
+    /// within `count` and `I` has to be the type the buffer stores.
+    pub fn map_range<I: IndexElement>(
+        &self,
+        range: Range<usize>,
+    ) -> MappedRange<'_, I> {
+        self.check_map_range::<I>(&range);
+        MappedRange::new(
+            self.renderer_id,
+            range.start,
+            range.len(),
+            MapAccess::Read,
+        )
+    }
+    //
+    /// Maps the indices in `range` for `MapAccess::Write` or
+    /// `MapAccess::ReadWrite`.
+    pub fn map_range_mut<I: IndexElement>(
+        &mut self,
+        range: Range<usize>,
+        access: MapAccess,
+    ) -> MappedRangeMut<'_, I> {
+        self.check_map_range::<I>(&range);
+        MappedRangeMut::new(self.renderer_id, range.start, range.len(), access)
+    }
+    //
+    fn check_map_range<I: IndexElement>(&self, range: &Range<usize>) {
+        assert_eq!(
+            I::INDEX_TYPE,
+            self.index_type,
+            "mapped with the wrong index type"
+        );
+        assert!(
+            range.start <= range.end && range.end <= self.count as usize,
+            "range {:?} is outside a buffer of {} indices",
+            range,
+            self.count
+        );
     }
 }
 
-impl From<&[u32]> for IndexBuffer {
-    fn from(data: &[u32]) -> Self {
-        let mut id = 0;
-        //
-        gl_call!(gl::GenBuffers(1, &mut id));
-        gl_call!(gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, id));
-        gl_call!(gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            (data.len() * std::mem::size_of::<u32>()) as isize,
-            data.as_ptr() as *mut c_void,
-            gl::STATIC_DRAW,
-        ));
-        //
-        Self {
-            renderer_id: id,
-            count: data.len() as i32,
-        }
+fn convert<I: IndexElement>(indices: &[u32]) -> Vec<I> {
+    indices.iter().map(|&i| I::from_u32(i)).collect()
+}
+
+impl<I: IndexElement> TryFrom<&[I]> for IndexBuffer {
+    type Error = GlCallError;
+    //
+    fn try_from(data: &[I]) -> Result<Self, GlCallError> {
+        Self::new(data)
     }
 }
 
-impl From<&[[u32; 3]]> for IndexBuffer {
-    fn from(data: &[[u32; 3]]) -> Self {
+impl<I: IndexElement> TryFrom<&[[I; 2]]> for IndexBuffer {
+    type Error = GlCallError;
+    //
+    fn try_from(data: &[[I; 2]]) -> Result<Self, GlCallError> {
+        Self::lines(data)
+    }
+}
+
+impl<I: IndexElement> TryFrom<&[[I; 3]]> for IndexBuffer {
+    type Error = GlCallError;
+    //
+    fn try_from(data: &[[I; 3]]) -> Result<Self, GlCallError> {
         unsafe {
-            let tmp = data.as_ptr() as *const u32;
+            let tmp = data.as_ptr() as *const I;
             let tmp = std::slice::from_raw_parts(tmp, data.len() * 3);
-            Self::from(tmp)
+            Self::new(tmp)
         }
     }
 }
 
 impl Drop for IndexBuffer {
     fn drop(&mut self) {
-        gl_call!(gl::DeleteBuffers(1, &self.renderer_id));
+        gl_backend::current().delete_buffer(self.renderer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+
+    #[test]
+    fn index_buffer_calls() {
+        let mock = install_mock();
+        let ib = IndexBuffer::try_from(&[[0u16, 1, 2], [2, 3, 0]][..]).unwrap();
+        ib.bind();
+        drop(ib);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "GenBuffers(-> 1)",
+                "BindBuffer(0x8893, 1)",
+                "BufferData(0x8893, 12 bytes, 0x88e4)",
+                "BindBuffer(0x8893, 1)",
+                "DeleteBuffers(1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn index_type_for_max_index() {
+        assert_eq!(IndexType::for_max_index(0), IndexType::U8);
+        assert_eq!(IndexType::for_max_index(255), IndexType::U8);
+        assert_eq!(IndexType::for_max_index(256), IndexType::U16);
+        assert_eq!(IndexType::for_max_index(65535), IndexType::U16);
+        assert_eq!(IndexType::for_max_index(65536), IndexType::U32);
+    }
+
+    #[test]
+    fn compact_picks_smallest_type() {
+        let mock = install_mock();
+        let ib = IndexBuffer::compact(&[0, 1, 300]).unwrap();
+        assert_eq!(ib.index_type(), IndexType::U16);
+        assert_eq!(ib.topology(), Topology::Triangles);
+        assert_eq!(ib.restart_index(), None);
+        assert_eq!(
+            call_log(&mock).last().unwrap(),
+            "BufferData(0x8893, 6 bytes, 0x88e4)"
+        );
+        //
+        let ib = IndexBuffer::compact(&[0, 1, 65536]).unwrap();
+        assert_eq!(ib.index_type(), IndexType::U32);
+        assert_eq!(ib.topology(), Topology::Triangles);
+    }
+
+    #[test]
+    fn restart_index_is_type_max() {
+        install_mock();
+        let ib = IndexBuffer::triangle_strips::<u8>(&[&[0, 1, 2], &[3, 4, 5]])
+            .unwrap();
+        assert_eq!(ib.restart_index(), Some(255));
+        let ib = IndexBuffer::line_strips::<u16>(&[&[0, 1], &[2, 3]]).unwrap();
+        assert_eq!(ib.restart_index(), Some(65535));
+        let ib = IndexBuffer::triangle_fans::<u32>(&[&[0, 1, 2], &[3, 4, 5]])
+            .unwrap();
+        assert_eq!(ib.restart_index(), Some(u32::MAX));
+        // a single part needs no restart
+        let ib = IndexBuffer::triangle_strips::<u16>(&[&[0, 1, 2]]).unwrap();
+        assert_eq!(ib.restart_index(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "255 is the primitive restart index")]
+    fn restart_index_cannot_be_used() {
+        install_mock();
+        let _ = IndexBuffer::triangle_strips::<u8>(&[&[0, 1, 255], &[2, 3]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be joined")]
+    fn joined_lists_are_rejected() {
+        install_mock();
+        let lists: &[&[u16]] = &[&[0, 1, 2], &[3, 4, 5]];
+        let _ = IndexBuffer::joined(Topology::Triangles, lists);
     }
 }
@@ -13,46 +13,262 @@ use nalgebra_glm as glm;
 
 #[macro_use]
 mod glcall;
+pub use glcall::{GlCallError, GlError};
+
+mod gl_backend;
+pub use gl_backend::{
+    set_current as set_gl_backend, GlBackend, GlCall, MockGl, NativeGl,
+};
+
+mod debug_output;
+pub use debug_output::{DebugOutput, StdoutLogger};
 
 mod renderer;
 pub use renderer::Renderer;
 
 mod vertex_buffer;
-pub use vertex_buffer::VertexBuffer;
+pub use vertex_buffer::{AnyVertexBuffer, BufferUsage, VertexBuffer};
 
 mod index_buffer;
-pub use index_buffer::IndexBuffer;
+pub use index_buffer::{IndexBuffer, IndexElement, IndexType, Topology};
+
+mod mapped_range;
+pub use mapped_range::{MapAccess, MappedRange, MappedRangeMut};
+
+mod ring_buffer;
+pub use ring_buffer::{Fence, RingBuffer, RING_FRAMES};
 
 mod vertex_array;
-pub use vertex_array::VertexArray;
+pub use vertex_array::{StrideMismatch, VertexArray};
 
 mod vertex_buffer_layout;
 pub use vertex_buffer_layout::VertexBufferLayout;
 
+#[macro_use]
+mod uniform_buffer;
+pub use uniform_buffer::{
+    std140_bytes, Std140, Std140Writer, UniformBlockError, UniformBuffer,
+};
+
 mod shader;
-pub use shader::Shader;
+pub use shader::{
+    compile_to_spirv, image_barrier, memory_barrier, set_program_cache_dir,
+    storage_barrier, type_name, AttributeInfo, LayoutMismatch, ProgramPipeline,
+    Reflection, Shader, ShaderCache, ShaderCacheStats, ShaderError,
+    ShaderProgram, ShaderStage, SourceLocation, SpecConstant, SpirvModule,
+    Uniform, UniformBlockInfo, UniformElement, UniformInfo, VariantKey,
+};
 
 mod texture;
-pub use texture::Texture;
+pub use texture::{Texture, TextureError};
+
+mod framebuffer;
+pub use framebuffer::{DepthStencilFormat, Framebuffer, FramebufferError};
+
+mod headless;
+pub use headless::HeadlessContext;
+
+mod golden;
+pub use golden::GoldenOptions;
+
+mod frame_clock;
+pub use frame_clock::FrameClock;
+
+mod screenshot;
+
+mod recorder;
+pub use recorder::Recorder;
 
 mod tests;
 pub use tests::{
-    test_clear_color::TestClearColor, test_texture2d::TestTexture2D, OGLTest,
-    TestMenu,
+    test_clear_color::TestClearColor, test_pipeline::TestProgramPipeline,
+    test_texture2d::TestTexture2D, OGLTest, TestMenu,
 };
 
 fn get_gl_version() {
-    println!(
-        "{}",
-        unsafe {
-            std::ffi::CStr::from_ptr(gl::GetString(gl::VERSION) as *const i8)
+    println!("{}", gl_backend::current().get_string(gl::VERSION));
+}
+
+/// Where linked program binaries are kept between runs, unless
+/// `--no-shader-cache` is passed.
+const SHADER_CACHE_DIR: &str = "cache/shaders";
+
+fn register_tests(test_menu: &mut TestMenu) {
+    test_menu.register_test::<TestClearColor>("Clear Color");
+    test_menu.register_test::<TestTexture2D>("Texture 2D");
+    if ProgramPipeline::is_supported() {
+        test_menu.register_test::<TestProgramPipeline>("Program Pipeline");
+    } else {
+        println!("Warning: skipping 'Program Pipeline', no separate shaders");
+    }
+}
+
+const HEADLESS_DELTA_TIME: f32 = 1.0 / 60.0;
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|value| &value[..])
+}
+
+/// Prints `message` and exits with a failure status.
+fn exit_with(message: impl std::fmt::Display) -> ! {
+    println!("{}", message);
+    std::process::exit(1);
+}
+
+const HEADLESS_USAGE: &str =
+    "Usage: --headless <test name> [frames] [output image]";
+
+/// `--headless <test name> [frames] [output image]` renders a test
+/// offscreen and exits, optionally saving the last frame. With
+/// `--record <file.gif or directory> [--record-every N]` the run is also
+/// captured like the recorder in the test menu does.
+fn run_headless(args: &[String], record: Option<(&str, u32)>) {
+    let name = args.get(0).unwrap_or_else(|| exit_with(HEADLESS_USAGE));
+    let frames = match args.get(1).map(|f| f.parse()) {
+        None => 1,
+        Some(Ok(frames)) => frames,
+        Some(Err(_)) => exit_with(HEADLESS_USAGE),
+    };
+    //
+    let context =
+        HeadlessContext::new(1280, 720).unwrap_or_else(|e| exit_with(e));
+    //
+    get_gl_version();
+    //
+    let mut test_menu = TestMenu::new();
+    register_tests(&mut test_menu);
+    //
+    if let Some((path, every)) = record {
+        let result = if path.ends_with(".gif") {
+            let delay_ms = (every as f32 * HEADLESS_DELTA_TIME * 1e3) as u32;
+            test_menu.recorder_mut().start_gif(path, every, delay_ms)
+        } else {
+            test_menu.recorder_mut().start_png_sequence(path, every)
+        };
+        if let Err(e) = result {
+            exit_with(e);
         }
-        .to_str()
-        .unwrap()
-    );
+    }
+    //
+    match headless::run_test(&mut test_menu, name, frames, HEADLESS_DELTA_TIME)
+    {
+        Ok(()) => println!("Rendered {} frames of '{}'", frames, name),
+        Err(e) => exit_with(e),
+    }
+    //
+    if test_menu.recorder_mut().is_recording() {
+        let captured = test_menu.recorder_mut().stop();
+        println!("Recorded {} frames", captured);
+    }
+    //
+    if let Some(path) = args.get(2) {
+        match screenshot::save(&context.read_pixels(), path) {
+            Ok(()) => println!("Saved {}", path),
+            Err(e) => exit_with(e),
+        }
+    }
+}
+
+/// `--golden [--update-golden]` compares every registered test against its
+/// reference image in `res/golden/`, or regenerates them.
+fn run_golden(update: bool) {
+    let context =
+        HeadlessContext::new(640, 480).unwrap_or_else(|e| exit_with(e));
+    //
+    let mut test_menu = TestMenu::new();
+    register_tests(&mut test_menu);
+    //
+    let options = GoldenOptions {
+        update: update,
+        ..GoldenOptions::default()
+    };
+    if !golden::run_all(&context, &mut test_menu, &options) {
+        std::process::exit(1);
+    }
+}
+
+/// `--compile-spirv <file.shader> [output dir]` compiles every stage to
+/// `<name>.<stage>.spv` without a GL context, so shaders can be checked in
+/// CI. The output defaults to the directory of the `.shader` file.
+fn run_compile_spirv(args: &[String]) {
+    let path = args.get(0).map(std::path::Path::new);
+    let name = path.and_then(|path| path.file_stem());
+    let dir = match args.get(1) {
+        Some(dir) => Some(dir.into()),
+        None => path
+            .and_then(|path| path.parent())
+            .map(|dir| dir.to_owned()),
+    };
+    let (filepath, name, dir) = match (args.get(0), name, dir) {
+        (Some(filepath), Some(name), Some(dir)) => {
+            (filepath, name.to_string_lossy(), dir)
+        }
+        _ => {
+            println!("Usage: --compile-spirv <file.shader> [output dir]");
+            std::process::exit(1);
+        }
+    };
+    //
+    let stages = match compile_to_spirv(filepath, &[]) {
+        Ok(stages) => stages,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for (stage, words) in stages {
+        let output = dir.join(format!("{}.{}.spv", name, stage.name()));
+        let bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect();
+        match std::fs::write(&output, bytes) {
+            Ok(()) => println!("Wrote {}", output.display()),
+            Err(e) => {
+                println!("Failed to write {}: {}", output.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--compile-spirv") {
+        run_compile_spirv(&args[i + 1..]);
+        return;
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--headless") {
+        let positional: Vec<String> = args[i + 1..]
+            .iter()
+            .take_while(|arg| !arg.starts_with("--"))
+            .cloned()
+            .collect();
+        let record = arg_value(&args, "--record").map(|path| {
+            let every = match arg_value(&args, "--record-every") {
+                None => 1,
+                Some(n) => n.parse().unwrap_or_else(|_| {
+                    exit_with("Usage: --record-every <frames>")
+                }),
+            };
+            (path, every)
+        });
+        run_headless(&positional, record);
+        return;
+    }
+    if args.iter().any(|arg| arg == "--golden") {
+        run_golden(args.iter().any(|arg| arg == "--update-golden"));
+        return;
+    }
+    //
+    let gl_debug = args.iter().any(|arg| arg == "--gl-debug");
+    if !args.iter().any(|arg| arg == "--no-shader-cache") {
+        set_program_cache_dir(Some(SHADER_CACHE_DIR.into()));
+    }
+    //
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
     //
     // glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
@@ -61,6 +277,7 @@ fn main() {
     // ));
     //
     glfw.window_hint(glfw::WindowHint::Resizable(false));
+    glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(gl_debug));
     // Create a windowed mode window and its OpenGL context
     let (mut window, events) = glfw
         .create_window(1280, 720, "OpenGL testing", glfw::WindowMode::Windowed)
@@ -71,10 +288,20 @@ fn main() {
     glfw.set_swap_interval(glfw::SwapInterval::Sync(0));
     window.set_all_polling(true);
     //
-    gl::load_with(|s| window.get_proc_address(s));
+    gl_backend::load_with(|s| window.get_proc_address(s));
     //
     get_gl_version();
     //
+    let _debug_output = if gl_debug {
+        let debug_output = DebugOutput::install(Box::new(StdoutLogger));
+        if debug_output.is_none() {
+            println!("Debug output unavailable, polling glGetError instead");
+        }
+        debug_output
+    } else {
+        None
+    };
+    //
     let renderer = Renderer {};
     //
     let mut imgui = imgui::Context::create();
@@ -82,16 +309,16 @@ fn main() {
     let mut imgui_glfw = ImguiGLFW::new(&mut imgui, &mut window);
     //
     let mut test_menu = TestMenu::new();
+    register_tests(&mut test_menu);
     //
-    test_menu.register_test::<TestClearColor>("Clear Color");
-    test_menu.register_test::<TestTexture2D>("Texture 2D");
+    let mut take_screenshot = false;
     //
     // Loop until the user closes the window
     while !window.should_close() {
         gl_call!(gl::ClearColor(0.0, 0.0, 0.0, 1.0));
         renderer.clear();
         //
-        test_menu.on_update(0.0);
+        test_menu.tick();
         test_menu.on_render();
         //
         let ui = imgui_glfw.frame(&mut window, &mut imgui);
@@ -100,13 +327,37 @@ fn main() {
         //
         imgui_glfw.draw(ui, &mut window);
         //
+        // The back buffer is undefined after the swap, so grab it before
+        if take_screenshot {
+            take_screenshot = false;
+            let (width, height) = window.get_framebuffer_size();
+            let img = screenshot::read_default_framebuffer(
+                width as u32,
+                height as u32,
+            );
+            let path = screenshot::timestamped_path();
+            match screenshot::save(&img, &path) {
+                Ok(()) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => println!("Failed to save screenshot: {}", e),
+            }
+        }
+        //
         // Swap front and back buffers
         window.swap_buffers();
         //
         // Poll for and process events
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
+            if let glfw::WindowEvent::Key(
+                glfw::Key::F12,
+                _,
+                glfw::Action::Press,
+                _,
+            ) = event
+            {
+                take_screenshot = true;
+            }
             imgui_glfw.handle_event(&mut imgui, &event);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,146 @@
+use super::gl_backend;
+
+use std::{
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+/// What a mapped range is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapAccess {
+    Read,
+    /// The previous contents of the range are discarded, so every element
+    /// should be written.
+    Write,
+    ReadWrite,
+}
+
+impl MapAccess {
+    pub fn gl_bits(&self) -> u32 {
+        match self {
+            MapAccess::Read => gl::MAP_READ_BIT,
+            MapAccess::Write => {
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT
+            }
+            MapAccess::ReadWrite => gl::MAP_READ_BIT | gl::MAP_WRITE_BIT,
+        }
+    }
+}
+
+/// A range of a buffer mapped for reading, unmapped again when dropped.
+/// Buffers are mapped through `GL_COPY_WRITE_BUFFER`, so the bound vertex
+/// array is left alone. An empty range is not mapped at all.
+pub struct MappedRange<'a, T> {
+    renderer_id: u32,
+    ptr: *mut T,
+    len: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> MappedRange<'a, T> {
+    /// Maps `len` elements of buffer `renderer_id` from element `offset`.
+    pub(crate) fn new(
+        renderer_id: u32,
+        offset: usize,
+        len: usize,
+        access: MapAccess,
+    ) -> Self {
+        // GL rejects mapping zero bytes
+        if len == 0 {
+            return Self {
+                renderer_id: renderer_id,
+                ptr: NonNull::dangling().as_ptr(),
+                len: 0,
+                _marker: PhantomData,
+            };
+        }
+        let backend = gl_backend::current();
+        backend.bind_buffer(gl::COPY_WRITE_BUFFER, renderer_id);
+        let ptr = backend.map_buffer_range(
+            gl::COPY_WRITE_BUFFER,
+            size_of::<T>() * offset,
+            size_of::<T>() * len,
+            access.gl_bits(),
+        );
+        assert!(!ptr.is_null(), "failed to map buffer {}", renderer_id);
+        assert_eq!(
+            ptr as usize % align_of::<T>(),
+            0,
+            "buffer {} was mapped misaligned",
+            renderer_id
+        );
+        //
+        Self {
+            renderer_id: renderer_id,
+            ptr: ptr as *mut T,
+            len: len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Deref for MappedRange<'a, T> {
+    type Target = [T];
+    //
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<'a, T> Drop for MappedRange<'a, T> {
+    fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        let backend = gl_backend::current();
+        backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.renderer_id);
+        if !backend.unmap_buffer(gl::COPY_WRITE_BUFFER) {
+            println!(
+                "Warning: buffer {} was corrupted while mapped",
+                self.renderer_id
+            );
+        }
+        backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+    }
+}
+
+/// A range of a buffer mapped for writing. With `MapAccess::Write` the
+/// elements start out undefined.
+pub struct MappedRangeMut<'a, T> {
+    range: MappedRange<'a, T>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> MappedRangeMut<'a, T> {
+    /// Panics on `MapAccess::Read`, see `MappedRange`.
+    pub(crate) fn new(
+        renderer_id: u32,
+        offset: usize,
+        len: usize,
+        access: MapAccess,
+    ) -> Self {
+        assert_ne!(access, MapAccess::Read, "a read mapping is not writable");
+        Self {
+            range: MappedRange::new(renderer_id, offset, len, access),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Deref for MappedRangeMut<'a, T> {
+    type Target = [T];
+    //
+    fn deref(&self) -> &[T] {
+        &self.range
+    }
+}
+
+impl<'a, T> DerefMut for MappedRangeMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.range.ptr, self.range.len)
+        }
+    }
+}
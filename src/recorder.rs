@@ -0,0 +1,118 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use image::{gif, Delay, Frame, ImageResult, RgbaImage};
+
+enum Output {
+    PngSequence(PathBuf),
+    Gif(gif::Encoder<BufWriter<File>>),
+}
+
+/// Captures every Nth rendered frame, either as numbered PNGs in a
+/// directory or as one animated GIF.
+pub struct Recorder {
+    output: Option<Output>,
+    every_nth: u32,
+    frame_delay_ms: u32,
+    frame_counter: u32,
+    captured: u32,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            output: None,
+            every_nth: 1,
+            frame_delay_ms: 0,
+            frame_counter: 0,
+            captured: 0,
+        }
+    }
+    //
+    fn start(&mut self, output: Output, every_nth: u32) {
+        self.output = Some(output);
+        self.every_nth = every_nth.max(1);
+        self.frame_counter = 0;
+        self.captured = 0;
+    }
+    //
+    /// Writes `frame_00000.png`, `frame_00001.png`, ... into `dir`.
+    pub fn start_png_sequence<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        every_nth: u32,
+    ) -> ImageResult<()> {
+        std::fs::create_dir_all(&dir)?;
+        self.start(Output::PngSequence(dir.as_ref().to_owned()), every_nth);
+        Ok(())
+    }
+    //
+    /// `frame_delay_ms` is how long each captured frame is shown for, so it
+    /// should be `every_nth` times the frame time for real-time playback.
+    pub fn start_gif<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        every_nth: u32,
+        frame_delay_ms: u32,
+    ) -> ImageResult<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        self.frame_delay_ms = frame_delay_ms;
+        self.start(Output::Gif(gif::Encoder::new(file)), every_nth);
+        Ok(())
+    }
+    //
+    /// Finishes the recording and returns how many frames were captured.
+    pub fn stop(&mut self) -> u32 {
+        // Dropping the GIF encoder writes the trailer
+        self.output = None;
+        self.captured
+    }
+    //
+    pub fn is_recording(&self) -> bool {
+        self.output.is_some()
+    }
+    //
+    pub fn captured(&self) -> u32 {
+        self.captured
+    }
+    //
+    /// Call once per rendered frame. `read_frame` is only invoked for the
+    /// frames that are kept.
+    pub fn on_frame<F>(&mut self, read_frame: F) -> ImageResult<()>
+    where
+        F: FnOnce() -> RgbaImage,
+    {
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        //
+        let keep = self.frame_counter % self.every_nth == 0;
+        self.frame_counter += 1;
+        if !keep {
+            return Ok(());
+        }
+        //
+        let img = read_frame();
+        match output {
+            Output::PngSequence(dir) => {
+                img.save(dir.join(format!("frame_{:05}.png", self.captured)))?
+            }
+            Output::Gif(encoder) => encoder.encode_frame(Frame::from_parts(
+                img,
+                0,
+                0,
+                Delay::from_numer_denom_ms(self.frame_delay_ms, 1),
+            ))?,
+        }
+        self.captured += 1;
+        //
+        Ok(())
+    }
+}
@@ -1,27 +1,133 @@
-use super::glcall;
-
-use std::ffi::c_void;
+use super::gl_backend;
 
 use gl;
 
-use super::{IndexBuffer, Shader, VertexArray};
+use super::{
+    AnyVertexBuffer, IndexBuffer, ShaderProgram, Topology, VertexArray,
+};
 pub struct Renderer {}
 
 impl Renderer {
-    pub fn draw(&self, va: &VertexArray, ib: &IndexBuffer, shader: &Shader) {
-        shader.bind();
+    /// `program` is either a `Shader` or a `ProgramPipeline`. The mode and
+    /// primitive restart state come from `ib`.
+    pub fn draw<P: ShaderProgram + ?Sized>(
+        &self,
+        va: &VertexArray,
+        ib: &IndexBuffer,
+        program: &P,
+    ) {
+        program.bind();
         va.bind();
         ib.bind();
         //
-        gl_call!(gl::DrawElements(
-            gl::TRIANGLES,
+        let backend = gl_backend::current();
+        match ib.restart_index() {
+            Some(index) => {
+                backend.enable(gl::PRIMITIVE_RESTART);
+                backend.primitive_restart_index(index);
+            }
+            None => backend.disable(gl::PRIMITIVE_RESTART),
+        }
+        backend.draw_elements(
+            ib.topology().gl_enum(),
             ib.count,
-            gl::UNSIGNED_INT,
-            0 as *const c_void
-        ));
+            ib.index_type().gl_enum(),
+        );
+    }
+    //
+    /// Draws every vertex written to `vb` without an index buffer.
+    pub fn draw_arrays<P: ShaderProgram + ?Sized>(
+        &self,
+        va: &VertexArray,
+        vb: &AnyVertexBuffer,
+        topology: Topology,
+        program: &P,
+    ) {
+        self.draw_vertices(va, topology, 0, vb.count(), program);
+    }
+    //
+    /// Draws `count` vertices from `first` on, e.g. a range pushed to a
+    /// `RingBuffer`.
+    pub fn draw_vertices<P: ShaderProgram + ?Sized>(
+        &self,
+        va: &VertexArray,
+        topology: Topology,
+        first: usize,
+        count: usize,
+        program: &P,
+    ) {
+        program.bind();
+        va.bind();
+        //
+        gl_backend::current().draw_arrays(
+            topology.gl_enum(),
+            first as i32,
+            count as i32,
+        );
     }
     //
     pub fn clear(&self) {
-        gl_call!(gl::Clear(gl::COLOR_BUFFER_BIT));
+        gl_backend::current().clear(gl::COLOR_BUFFER_BIT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{
+        call_log, install_mock, COLOR_SHADER,
+    };
+    use super::super::{Shader, VertexBuffer};
+
+    use std::convert::TryFrom;
+
+    #[test]
+    fn draw_arrays_uses_topology() {
+        let mock = install_mock();
+        let vb = VertexBuffer::try_from(&[[0.0f32; 2]; 3][..]).unwrap();
+        let va = VertexArray::new().unwrap();
+        let shader = Shader::from_file(COLOR_SHADER).unwrap();
+        mock.clear_calls();
+        Renderer {}.draw_arrays(&va, &vb, Topology::LineStrip, &shader);
+        //
+        assert_eq!(call_log(&mock).last().unwrap(), "DrawArrays(0x3, 0, 3)");
+    }
+
+    #[test]
+    fn joined_strips_enable_primitive_restart() {
+        let mock = install_mock();
+        let va = VertexArray::new().unwrap();
+        let shader = Shader::from_file(COLOR_SHADER).unwrap();
+        let strips: &[&[u16]] = &[&[0, 1, 2, 3], &[4, 5, 6]];
+        let ib = IndexBuffer::triangle_strips(strips).unwrap();
+        mock.clear_calls();
+        Renderer {}.draw(&va, &ib, &shader);
+        //
+        let log = call_log(&mock);
+        assert_eq!(
+            log[log.len() - 3..],
+            [
+                "Enable(0x8f9d)",
+                "PrimitiveRestartIndex(65535)",
+                "DrawElements(0x5, 8, 0x1403)",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_disable_primitive_restart() {
+        let mock = install_mock();
+        let va = VertexArray::new().unwrap();
+        let shader = Shader::from_file(COLOR_SHADER).unwrap();
+        let ib = IndexBuffer::try_from(&[[0u16, 1, 2]][..]).unwrap();
+        mock.clear_calls();
+        Renderer {}.draw(&va, &ib, &shader);
+        //
+        let log = call_log(&mock);
+        assert_eq!(
+            log[log.len() - 2..],
+            ["Disable(0x8f9d)", "DrawElements(0x4, 3, 0x1403)"]
+        );
     }
 }
@@ -0,0 +1,132 @@
+use super::gl_backend;
+
+use super::VertexBuffer;
+
+/// How many frames of data a `RingBuffer` holds: one being written, and up
+/// to two the GPU may still be reading.
+pub const RING_FRAMES: usize = 3;
+
+/// How long a single `glClientWaitSync` call may block, in nanoseconds.
+const FENCE_WAIT_TIMEOUT: u64 = 1_000_000;
+
+/// Marks a point in the GPU command stream, so the CPU can wait for every
+/// command issued before it to finish.
+pub struct Fence {
+    sync: usize,
+}
+
+impl Fence {
+    pub fn new() -> Self {
+        Self {
+            sync: gl_backend::current().fence_sync(),
+        }
+    }
+    //
+    /// Blocks until the GPU has passed the fence, flushing the commands
+    /// before it so the wait cannot deadlock.
+    pub fn wait(&self) {
+        let backend = gl_backend::current();
+        loop {
+            match backend.client_wait_sync(
+                self.sync,
+                gl::SYNC_FLUSH_COMMANDS_BIT,
+                FENCE_WAIT_TIMEOUT,
+            ) {
+                gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => return,
+                gl::TIMEOUT_EXPIRED => continue,
+                _ => panic!("glClientWaitSync failed"),
+            }
+        }
+    }
+}
+
+impl Default for Fence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        gl_backend::current().delete_sync(self.sync);
+    }
+}
+
+/// Streams per-frame vertex data through a persistently mapped buffer split
+/// into `RING_FRAMES` regions. Each frame writes its own region, and a
+/// fence makes sure the GPU is done with a region before it is reused, so
+/// neither side waits on the other in the common case.
+///
+/// ```ignore
+/// ring.begin_frame();
+/// let first = ring.push(&vertices).unwrap();
+/// let count = vertices.len();
+/// renderer.draw_vertices(&va, Topology::Triangles, first, count, &shader);
+/// ring.end_frame();
+/// ```
+pub struct RingBuffer<T: Copy + 'static> {
+    buffer: VertexBuffer<T>,
+    frame_capacity: usize,
+    frame: usize,
+    cursor: usize,
+    fences: Vec<Option<Fence>>,
+}
+
+impl<T: Copy + 'static> RingBuffer<T> {
+    /// Room for `frame_capacity` elements per frame. `None` without GL 4.4
+    /// or `GL_ARB_buffer_storage`, or if the storage cannot be mapped.
+    pub fn new(frame_capacity: usize) -> Option<Self> {
+        let buffer = VertexBuffer::persistent(frame_capacity * RING_FRAMES)?;
+        Some(Self {
+            buffer: buffer,
+            frame_capacity: frame_capacity,
+            frame: 0,
+            cursor: 0,
+            fences: (0..RING_FRAMES).map(|_| None).collect(),
+        })
+    }
+    //
+    /// Waits until the GPU no longer reads the region of this frame and
+    /// starts filling it from the beginning.
+    pub fn begin_frame(&mut self) {
+        if let Some(fence) = self.fences[self.frame].take() {
+            fence.wait();
+        }
+        self.cursor = 0;
+    }
+    //
+    /// Copies `data` into this frame's region and returns the index of its
+    /// first element in the whole buffer, to draw from. `None` when the
+    /// region is full.
+    pub fn push(&mut self, data: &[T]) -> Option<usize> {
+        if self.cursor + data.len() > self.frame_capacity {
+            return None;
+        }
+        let first = self.frame * self.frame_capacity + self.cursor;
+        let slice = self.buffer.persistent_slice().unwrap();
+        slice[first..first + data.len()].copy_from_slice(data);
+        self.cursor += data.len();
+        Some(first)
+    }
+    //
+    /// Fences the draws reading this frame's region and moves on to the
+    /// next one. Call after the last draw using the frame's data.
+    pub fn end_frame(&mut self) {
+        self.fences[self.frame] = Some(Fence::new());
+        self.frame = (self.frame + 1) % RING_FRAMES;
+    }
+    //
+    /// Elements pushed since `begin_frame`.
+    pub fn frame_len(&self) -> usize {
+        self.cursor
+    }
+    //
+    pub fn frame_capacity(&self) -> usize {
+        self.frame_capacity
+    }
+    //
+    /// The underlying buffer, for `VertexArray::add_buffer`.
+    pub fn buffer(&self) -> &VertexBuffer<T> {
+        &self.buffer
+    }
+}
@@ -0,0 +1,97 @@
+use super::gl_backend;
+
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageResult, RgbaImage};
+
+use super::Framebuffer;
+
+/// Reads a rectangle of the bound read framebuffer. GL returns the bottom
+/// row first, so the rows are flipped back to undo the flip `load_img` does
+/// on upload.
+pub fn read_pixels(x: i32, y: i32, width: u32, height: u32) -> RgbaImage {
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    gl_backend::current().read_pixels(
+        x,
+        y,
+        width as i32,
+        height as i32,
+        &mut pixels,
+    );
+    //
+    let img = RgbaImage::from_raw(width, height, pixels).unwrap();
+    image::imageops::flip_vertical(&img)
+}
+
+/// Reads the back buffer of the window, i.e. what will be shown on the next
+/// swap.
+pub fn read_default_framebuffer(width: u32, height: u32) -> RgbaImage {
+    let backend = gl_backend::current();
+    backend.bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+    backend.read_buffer(gl::BACK);
+    read_pixels(0, 0, width, height)
+}
+
+/// Reads the viewport of whatever framebuffer is currently being drawn to.
+pub fn read_current_framebuffer() -> RgbaImage {
+    let backend = gl_backend::current();
+    let viewport = backend.get_viewport();
+    let draw_framebuffer =
+        backend.get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as u32;
+    //
+    backend.bind_framebuffer(gl::READ_FRAMEBUFFER, draw_framebuffer);
+    backend.read_buffer(if draw_framebuffer == 0 {
+        gl::BACK
+    } else {
+        gl::COLOR_ATTACHMENT0
+    });
+    read_pixels(
+        viewport[0],
+        viewport[1],
+        viewport[2] as u32,
+        viewport[3] as u32,
+    )
+}
+
+impl Framebuffer {
+    pub fn read_pixels(&self, attachment: usize) -> RgbaImage {
+        let backend = gl_backend::current();
+        backend.bind_framebuffer(gl::READ_FRAMEBUFFER, self.renderer_id());
+        backend.read_buffer(gl::COLOR_ATTACHMENT0 + attachment as u32);
+        let img = read_pixels(0, 0, self.width(), self.height());
+        backend.bind_framebuffer(gl::READ_FRAMEBUFFER, 0);
+        img
+    }
+}
+
+/// Saves in the format given by the extension. JPEG has no alpha channel,
+/// so it is dropped for `.jpg`/`.jpeg`.
+pub fn save<P: AsRef<Path>>(img: &RgbaImage, path: P) -> ImageResult<()> {
+    let path = path.as_ref();
+    let is_jpeg =
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| {
+                ext.eq_ignore_ascii_case("jpg")
+                    || ext.eq_ignore_ascii_case("jpeg")
+            });
+    //
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    //
+    if is_jpeg {
+        DynamicImage::ImageRgba8(img.clone()).to_rgb().save(path)
+    } else {
+        img.save(path)
+    }
+}
+
+/// `screenshots/screenshot_<unix time in ms>.png`
+pub fn timestamped_path() -> PathBuf {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    PathBuf::from(format!("screenshots/screenshot_{}.png", millis))
+}
@@ -0,0 +1,202 @@
+use super::super::gl_backend;
+
+use super::{mark_separable, StageSource};
+
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Marks a file written by `store`, followed by the binary format and the
+/// binary itself.
+const MAGIC: &[u8; 4] = b"GLPB";
+
+/// 64-bit FNV-1a, whose output, unlike that of `DefaultHasher`, is the same
+/// on every Rust release and platform, so cache file names stay valid.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+    //
+    /// Hashes `bytes` followed by 0xff, which never occurs in UTF-8, so
+    /// consecutive strings cannot run into each other.
+    fn write_str(&mut self, s: &str) {
+        for &byte in s.as_bytes().iter().chain(&[0xff]) {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+thread_local! {
+    static CACHE_DIR: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// Enables the on-disk program binary cache in `dir`, or disables it with
+/// `None`. It is disabled until this is called.
+pub fn set_program_cache_dir(dir: Option<PathBuf>) {
+    CACHE_DIR.with(|cache_dir| *cache_dir.borrow_mut() = dir);
+}
+
+/// Where the binary for `stages` lives, or `None` when the cache is
+/// disabled or the driver has no binary formats. The name hashes the
+/// preprocessed sources together with the driver's vendor, renderer and
+/// version, so a driver update never loads a stale binary.
+pub(super) fn cache_path(
+    stages: &[StageSource],
+    separable: bool,
+) -> Option<PathBuf> {
+    let dir = CACHE_DIR.with(|cache_dir| cache_dir.borrow().clone())?;
+    //
+    let backend = gl_backend::current();
+    if backend.program_binary_formats().is_empty() {
+        return None;
+    }
+    //
+    let mut hasher = Fnv1a::new();
+    hasher.write_str(if separable { "separable" } else { "" });
+    for stage in stages {
+        hasher.write_str(stage.stage.name());
+        hasher.write_str(&stage.source.text());
+    }
+    for &name in &[gl::VENDOR, gl::RENDERER, gl::VERSION] {
+        hasher.write_str(&backend.get_string(name));
+    }
+    //
+    Some(dir.join(format!("{:016x}.bin", hasher.0)))
+}
+
+fn read_binary(path: &Path) -> Option<(u32, Vec<u8>)> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.len() < 8 || &bytes[..4] != MAGIC {
+        return None;
+    }
+    let mut format = [0; 4];
+    format.copy_from_slice(&bytes[4..8]);
+    Some((u32::from_le_bytes(format), bytes[8..].to_vec()))
+}
+
+/// Creates a program from the binary at `path`. A binary the driver
+/// rejects is deleted, and `None` tells the caller to compile from source.
+pub(super) fn load(path: &Path, separable: bool) -> Option<u32> {
+    let (format, binary) = read_binary(path)?;
+    //
+    let backend = gl_backend::current();
+    if !backend.program_binary_formats().contains(&format) {
+        println!(
+            "Warning: program binary {} has format 0x{:x}, which the driver \
+             does not take, compiling from source",
+            path.display(),
+            format
+        );
+        let _ = fs::remove_file(path);
+        return None;
+    }
+    //
+    let program = backend.create_program();
+    if separable {
+        mark_separable(program);
+    }
+    let loaded = backend.program_binary(program, format, &binary).is_ok()
+        && backend.get_program_iv(program, gl::LINK_STATUS) != 0;
+    //
+    if !loaded {
+        println!(
+            "Warning: program binary {} was rejected, compiling from source",
+            path.display()
+        );
+        backend.delete_program(program);
+        let _ = fs::remove_file(path);
+        return None;
+    }
+    //
+    Some(program)
+}
+
+/// Asks the driver to keep the binary of `program` retrievable. Has to be
+/// called before it is linked.
+pub(super) fn mark_retrievable(program: u32) {
+    gl_backend::current().program_parameter_i(
+        program,
+        gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+        gl::TRUE as i32,
+    );
+}
+
+fn write_binary(path: &Path, format: u32, binary: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut bytes = Vec::with_capacity(8 + binary.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&format.to_le_bytes());
+    bytes.extend_from_slice(binary);
+    fs::write(path, bytes)
+}
+
+/// Saves the binary of a linked `program`. Failing to write it only costs
+/// a compile next time, so it is just a warning.
+pub(super) fn store(path: &Path, program: u32) {
+    let (format, binary) = gl_backend::current().get_program_binary(program);
+    if binary.is_empty() {
+        return;
+    }
+    if let Err(e) = write_binary(path, format, &binary) {
+        println!(
+            "Warning: could not write program binary {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::super::gl_backend::tests::{call_log, install_mock};
+    use super::super::super::GlError;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "binary_cache_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        let mut hasher = Fnv1a::new();
+        hasher.write_str("vertex");
+        assert_eq!(hasher.0, 0xca71_b156_3dae_5cc8);
+    }
+
+    #[test]
+    fn rejected_binary_falls_back_to_source() {
+        let mock = install_mock();
+        mock.set_query_result(gl::NUM_PROGRAM_BINARY_FORMATS, 1);
+        mock.fail_on("ProgramBinary", GlError::InvalidEnum);
+        let path = temp_path("rejected.bin");
+        write_binary(&path, 1, &[1, 2, 3]).unwrap();
+        //
+        assert_eq!(load(&path, false), None);
+        assert!(!path.exists());
+        assert_eq!(call_log(&mock).last().unwrap(), "DeleteProgram(1)");
+    }
+
+    #[test]
+    fn unknown_format_is_not_loaded() {
+        let mock = install_mock();
+        mock.set_query_result(gl::NUM_PROGRAM_BINARY_FORMATS, 1);
+        let path = temp_path("unknown.bin");
+        write_binary(&path, 7, &[1, 2, 3]).unwrap();
+        //
+        assert_eq!(load(&path, false), None);
+        assert!(!path.exists());
+        assert!(!mock.call_names().contains(&"CreateProgram"));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{Shader, ShaderError};
+
+/// A `.shader` file together with the keywords it was compiled with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VariantKey {
+    pub filepath: String,
+    pub keywords: Vec<String>,
+}
+
+impl VariantKey {
+    /// Keywords are sorted and deduplicated, so `[A, B]` and `[B, A, A]`
+    /// name the same variant.
+    pub fn new(filepath: &str, keywords: &[&str]) -> Self {
+        let mut keywords: Vec<_> =
+            keywords.iter().map(|&k| k.to_owned()).collect();
+        keywords.sort();
+        keywords.dedup();
+        //
+        Self {
+            filepath: filepath.to_owned(),
+            keywords: keywords,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShaderCacheStats {
+    pub hits: u32,
+    pub misses: u32,
+    pub failures: u32,
+    pub compile_time: Duration,
+}
+
+/// Compiles each variant of a `.shader` file once. A variant is selected by
+/// a set of keywords, each of which is defined as `1` in every stage, so
+/// the source can switch on them with `#ifdef`.
+pub struct ShaderCache {
+    variants: HashMap<VariantKey, Shader>,
+    stats: ShaderCacheStats,
+}
+
+impl ShaderCache {
+    pub fn new() -> Self {
+        Self {
+            variants: HashMap::new(),
+            stats: ShaderCacheStats::default(),
+        }
+    }
+    //
+    /// Returns the variant, compiling it on first use. Failed compilations
+    /// are not cached, so the next call tries again.
+    pub fn get(
+        &mut self,
+        filepath: &str,
+        keywords: &[&str],
+    ) -> Result<&mut Shader, ShaderError> {
+        let key = VariantKey::new(filepath, keywords);
+        //
+        if self.variants.contains_key(&key) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            //
+            let defines: Vec<_> =
+                key.keywords.iter().map(|k| (&k[..], "1")).collect();
+            let start = Instant::now();
+            let result = Shader::with_defines(filepath, &defines);
+            self.stats.compile_time += start.elapsed();
+            //
+            match result {
+                Ok(shader) => {
+                    self.variants.insert(key.clone(), shader);
+                }
+                Err(e) => {
+                    self.stats.failures += 1;
+                    return Err(e);
+                }
+            }
+        }
+        //
+        Ok(self.variants.get_mut(&key).unwrap())
+    }
+    //
+    /// A variant `get` compiled earlier, without counting a hit.
+    pub fn variant_mut(&mut self, key: &VariantKey) -> Option<&mut Shader> {
+        self.variants.get_mut(key)
+    }
+    //
+    /// Polls every cached variant for changes to its files.
+    pub fn reload_if_changed(&mut self) {
+        for shader in self.variants.values_mut() {
+            shader.reload_if_changed();
+        }
+    }
+    //
+    pub fn clear(&mut self) {
+        self.variants.clear();
+    }
+    //
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+    //
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+    //
+    pub fn stats(&self) -> ShaderCacheStats {
+        self.stats
+    }
+    //
+    pub fn variants(&self) -> impl Iterator<Item = (&VariantKey, &Shader)> {
+        self.variants.iter()
+    }
+}
@@ -0,0 +1,1072 @@
+use super::{gl_backend, glcall};
+
+mod binary_cache;
+pub use binary_cache::set_program_cache_dir;
+
+mod cache;
+pub use cache::{ShaderCache, ShaderCacheStats, VariantKey};
+
+mod preprocessor;
+pub use preprocessor::SourceLocation;
+
+mod pipeline;
+pub use pipeline::ProgramPipeline;
+
+mod uniform;
+pub use uniform::{Uniform, UniformElement};
+
+mod spirv;
+pub use spirv::{compile_to_spirv, SpecConstant, SpirvModule};
+
+mod reflection;
+pub use reflection::{
+    type_name, AttributeInfo, LayoutMismatch, Reflection, UniformBlockInfo,
+    UniformInfo,
+};
+
+use preprocessor::{Includes, MappedSource};
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    uniform_buffer::{check_block_size, UniformBlockError},
+    GlCallError, Std140, VertexBufferLayout,
+};
+
+/// How often `reload_if_changed` looks at the file's modification time.
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct Shader {
+    filepath: String,
+    defines: Vec<(String, String)>,
+    renderer_id: u32,
+    stages: Vec<ShaderStage>,
+    separable: bool,
+    /// The modules a SPIR-V program was created from, empty for GLSL.
+    spirv: Vec<SpirvModule>,
+    reflection: Reflection,
+    uniform_location_cache: HashMap<String, i32>,
+    type_warnings: HashSet<String>,
+    /// Block name, binding point and std140 size, restored after reloads.
+    block_bindings: Vec<(String, u32, usize)>,
+    //
+    files: Vec<String>,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    reload_error: Option<ShaderError>,
+}
+
+/// The most recent modification time of `files`, so editing an included
+/// file also triggers a reload.
+fn files_modified(files: &[String]) -> Option<SystemTime> {
+    files
+        .iter()
+        .filter_map(|file| {
+            std::fs::metadata(file).and_then(|m| m.modified()).ok()
+        })
+        .max()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vertex" => Some(ShaderStage::Vertex),
+            "tess_control" => Some(ShaderStage::TessControl),
+            "tess_evaluation" => Some(ShaderStage::TessEvaluation),
+            "geometry" => Some(ShaderStage::Geometry),
+            "fragment" => Some(ShaderStage::Fragment),
+            "compute" => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+    //
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::TessControl => "tess_control",
+            ShaderStage::TessEvaluation => "tess_evaluation",
+            ShaderStage::Geometry => "geometry",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Compute => "compute",
+        }
+    }
+    //
+    fn gl_type(&self) -> u32 {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+            ShaderStage::Compute => gl::COMPUTE_SHADER,
+        }
+    }
+    //
+    /// The bit for this stage in `glUseProgramStages`.
+    fn gl_bit(&self) -> u32 {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER_BIT,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER_BIT,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER_BIT,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER_BIT,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER_BIT,
+            ShaderStage::Compute => gl::COMPUTE_SHADER_BIT,
+        }
+    }
+}
+
+/// One line of a compiler info log, with the line number translated to the
+/// file and line it came from when the driver reported one.
+#[derive(Debug, Clone)]
+pub struct CompileMessage {
+    pub location: Option<SourceLocation>,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io {
+        filepath: String,
+        error: io::Error,
+    },
+    Parse {
+        filepath: String,
+        line: usize,
+        message: String,
+    },
+    Compile {
+        filepath: String,
+        stage: ShaderStage,
+        messages: Vec<CompileMessage>,
+    },
+    /// The file parsed, but its stages do not form a valid program.
+    Stages {
+        filepath: String,
+        message: String,
+    },
+    Spirv {
+        filepath: String,
+        message: String,
+    },
+    Link {
+        filepath: String,
+        log: String,
+    },
+    /// Only returned by `ProgramPipeline::validate`.
+    Validate {
+        filepath: String,
+        log: String,
+    },
+    /// The context lacks a feature, e.g. separate shader objects.
+    Unsupported {
+        feature: &'static str,
+    },
+    Gl(GlCallError),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Io { filepath, error } => {
+                write!(f, "Failed to read shader '{}': {}", filepath, error)
+            }
+            ShaderError::Parse {
+                filepath,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", filepath, line, message),
+            ShaderError::Compile {
+                filepath,
+                stage,
+                messages,
+            } => {
+                writeln!(f, "Failed to compile {} shader", stage.name())?;
+                for message in messages {
+                    match &message.location {
+                        Some(location) => {
+                            writeln!(f, "{}: {}", location, message.message)?
+                        }
+                        None => {
+                            writeln!(f, "{}: {}", filepath, message.message)?
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ShaderError::Stages { filepath, message } => {
+                write!(f, "Invalid stages in '{}': {}", filepath, message)
+            }
+            ShaderError::Spirv { filepath, message } => {
+                write!(f, "Invalid SPIR-V '{}': {}", filepath, message)
+            }
+            ShaderError::Link { filepath, log } => {
+                write!(f, "Failed to link '{}':\n{}", filepath, log)
+            }
+            ShaderError::Validate { filepath, log } => {
+                write!(f, "Failed to validate '{}':\n{}", filepath, log)
+            }
+            ShaderError::Unsupported { feature } => {
+                write!(f, "{} is not supported by this context", feature)
+            }
+            ShaderError::Gl(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderError::Io { error, .. } => Some(error),
+            ShaderError::Gl(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<GlCallError> for ShaderError {
+    fn from(error: GlCallError) -> Self {
+        ShaderError::Gl(error)
+    }
+}
+
+/// The preprocessed source of one `#shader` section.
+struct StageSource {
+    stage: ShaderStage,
+    source: MappedSource,
+}
+
+fn parse_digits(s: &str) -> Option<(usize, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().ok().map(|n| (n, &s[end..]))
+}
+
+/// Finds the source line number in a log line. Drivers disagree on the
+/// format: Mesa writes `0:12(5): error`, NVIDIA `0(12) : error` and AMD and
+/// Intel `ERROR: 0:12: ...`.
+fn parse_log_line_number(line: &str) -> Option<(usize, String)> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let (_, rest) = parse_digits(&line[start..])?;
+    //
+    let (number, rest) = if rest.starts_with(':') {
+        parse_digits(&rest[1..])?
+    } else if rest.starts_with('(') {
+        parse_digits(&rest[1..])?
+    } else {
+        return None;
+    };
+    //
+    let rest = rest.trim_start_matches(|c: char| {
+        c.is_ascii_digit() || c.is_whitespace() || "():".contains(c)
+    });
+    let prefix = line[..start].trim().trim_end_matches(':');
+    let message = if prefix.is_empty() {
+        rest.to_owned()
+    } else {
+        format!("{}: {}", prefix, rest)
+    };
+    Some((number, message))
+}
+
+fn parse_compile_log(log: &str, source: &MappedSource) -> Vec<CompileMessage> {
+    log.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match parse_log_line_number(line) {
+            Some((number, message)) => CompileMessage {
+                location: source.location(number).cloned(),
+                message: message,
+            },
+            None => CompileMessage {
+                location: None,
+                message: line.trim().to_owned(),
+            },
+        })
+        .collect()
+}
+
+fn compile_shader(
+    filepath: &str,
+    stage: &StageSource,
+) -> Result<u32, ShaderError> {
+    let backend = gl_backend::current();
+    //
+    let id = backend.create_shader(stage.stage.gl_type());
+    backend.shader_source(id, &stage.source.text());
+    backend.compile_shader(id);
+    //
+    let result = backend.get_shader_iv(id, gl::COMPILE_STATUS);
+    //
+    if result == 0 {
+        let log = backend.get_shader_info_log(id);
+        backend.delete_shader(id);
+        return Err(ShaderError::Compile {
+            filepath: filepath.to_owned(),
+            stage: stage.stage,
+            messages: parse_compile_log(&log, &stage.source),
+        });
+    }
+    //
+    Ok(id)
+}
+
+/// Compiles and links `stages` from source.
+fn link_program(
+    filepath: &str,
+    stages: &[StageSource],
+    separable: bool,
+    retrievable: bool,
+) -> Result<u32, ShaderError> {
+    let mut shaders = Vec::new();
+    for stage in stages {
+        match compile_shader(filepath, stage) {
+            Ok(id) => shaders.push(id),
+            Err(e) => {
+                delete_shaders(&shaders);
+                return Err(e);
+            }
+        }
+    }
+    link_shaders(filepath, &shaders, separable, retrievable)
+}
+
+fn delete_shaders(shaders: &[u32]) {
+    let backend = gl_backend::current();
+    for &id in shaders {
+        backend.delete_shader(id);
+    }
+}
+
+/// Links compiled shader objects into a program and deletes them.
+fn link_shaders(
+    filepath: &str,
+    shaders: &[u32],
+    separable: bool,
+    retrievable: bool,
+) -> Result<u32, ShaderError> {
+    let backend = gl_backend::current();
+    //
+    let program = backend.create_program();
+    for &id in shaders {
+        backend.attach_shader(program, id);
+    }
+    if separable {
+        mark_separable(program);
+    }
+    if retrievable {
+        binary_cache::mark_retrievable(program);
+    }
+    backend.link_program(program);
+    //
+    delete_shaders(shaders);
+    //
+    if backend.get_program_iv(program, gl::LINK_STATUS) == 0 {
+        let log = backend.get_program_info_log(program);
+        backend.delete_program(program);
+        return Err(ShaderError::Link {
+            filepath: filepath.to_owned(),
+            log: log,
+        });
+    }
+    //
+    Ok(program)
+}
+
+/// Deletes the program if it cannot run in the current state.
+/// Only warns, since whether a program validates depends on the state at
+/// draw time, e.g. the bound vertex array and which units samplers use.
+fn validate_program(filepath: &str, program: u32) {
+    let backend = gl_backend::current();
+    backend.validate_program(program);
+    if backend.get_program_iv(program, gl::VALIDATE_STATUS) == 0 {
+        println!(
+            "Warning: '{}' does not validate in the current state:\n{}",
+            filepath,
+            backend.get_program_info_log(program)
+        );
+    }
+}
+
+/// Lets the program be bound to some stages of a `ProgramPipeline`. Has to
+/// be set before the program is linked.
+fn mark_separable(program: u32) {
+    gl_backend::current().program_parameter_i(
+        program,
+        gl::PROGRAM_SEPARABLE,
+        gl::TRUE as i32,
+    );
+}
+
+/// Runs `create`, turning a GL error raised on the way into `ShaderError::Gl`
+/// after deleting the program it made anyway.
+fn catch_gl_errors(
+    create: impl FnOnce() -> Result<u32, ShaderError>,
+) -> Result<u32, ShaderError> {
+    match glcall::capture_errors(create) {
+        (result, None) => result,
+        (result, Some(error)) => {
+            if let Ok(program) = result {
+                gl_backend::current().delete_program(program);
+            }
+            Err(ShaderError::Gl(error))
+        }
+    }
+}
+
+/// Loads the program from the binary cache when enabled, falling back to
+/// compiling from source and caching the result. Separable programs are
+/// validated as part of their pipeline instead.
+fn create_shader(
+    filepath: &str,
+    stages: &[StageSource],
+    separable: bool,
+) -> Result<u32, ShaderError> {
+    catch_gl_errors(|| {
+        let cache_path = binary_cache::cache_path(stages, separable);
+        let cached = cache_path
+            .as_ref()
+            .and_then(|path| binary_cache::load(path, separable));
+        let program = match cached {
+            Some(program) => program,
+            None => {
+                let program = link_program(
+                    filepath,
+                    stages,
+                    separable,
+                    cache_path.is_some(),
+                )?;
+                if let Some(path) = &cache_path {
+                    binary_cache::store(path, program);
+                }
+                program
+            }
+        };
+        //
+        if !separable {
+            validate_program(filepath, program);
+        }
+        Ok(program)
+    })
+}
+
+/// Checks the stages can be linked together: compute on its own, otherwise a
+/// vertex shader and a tessellation evaluation shader whenever there is a
+/// tessellation control shader. A separable program only needs to avoid
+/// mixing compute with other stages, the pipeline supplies the rest.
+fn validate_stages(
+    filepath: &str,
+    stages: &[ShaderStage],
+    separable: bool,
+) -> Result<(), ShaderError> {
+    let has = |stage| stages.contains(&stage);
+    let message = if stages.is_empty() {
+        Some("no shader stages")
+    } else if has(ShaderStage::Compute) {
+        if stages.len() > 1 {
+            Some("compute shaders cannot be combined with other stages")
+        } else {
+            None
+        }
+    } else if separable {
+        None
+    } else if !has(ShaderStage::Vertex) {
+        Some("missing vertex shader")
+    } else if has(ShaderStage::TessControl) && !has(ShaderStage::TessEvaluation)
+    {
+        Some("tess_control requires a tess_evaluation shader")
+    } else {
+        None
+    };
+    //
+    match message {
+        Some(message) => Err(ShaderError::Stages {
+            filepath: filepath.to_owned(),
+            message: message.to_owned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Splits a `.shader` file into its stages, expanding includes and adding
+/// `defines` to every stage. Also returns every file that was read.
+fn parse_shader(
+    filepath: &str,
+    defines: &[(String, String)],
+    separable: bool,
+) -> Result<(Vec<StageSource>, Vec<String>), ShaderError> {
+    let io_error = |error| ShaderError::Io {
+        filepath: filepath.to_owned(),
+        error: error,
+    };
+    //
+    let file = File::open(filepath).map_err(io_error)?;
+    let mut includes = Includes::new(filepath)?;
+    //
+    // Anything before the first directive counts as vertex shader source
+    let mut stages = vec![StageSource {
+        stage: ShaderStage::Vertex,
+        source: MappedSource::default(),
+    }];
+    //
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        let line_number = i + 1;
+        //
+        if line.starts_with("#shader") {
+            let name = line["#shader".len()..].trim();
+            let stage = ShaderStage::from_name(name).ok_or_else(|| {
+                ShaderError::Parse {
+                    filepath: filepath.to_owned(),
+                    line: line_number,
+                    message: format!("unknown shader stage '{}'", name),
+                }
+            })?;
+            //
+            // Drops the implicit vertex section if nothing was written to it
+            stages.retain(|s| !s.source.is_blank());
+            if stages.iter().any(|s| s.stage == stage) {
+                return Err(ShaderError::Parse {
+                    filepath: filepath.to_owned(),
+                    line: line_number,
+                    message: format!("duplicate {} shader", stage.name()),
+                });
+            }
+            stages.push(StageSource {
+                stage: stage,
+                source: MappedSource::default(),
+            });
+            includes.next_stage();
+        } else {
+            let active = stages.last_mut().unwrap();
+            includes.process_line(
+                &mut active.source,
+                filepath,
+                line_number,
+                &line,
+            )?;
+        }
+    }
+    //
+    stages.retain(|s| !s.source.is_blank());
+    for stage in &mut stages {
+        stage.source.inject_defines(defines);
+    }
+    //
+    let kinds: Vec<_> = stages.iter().map(|s| s.stage).collect();
+    validate_stages(filepath, &kinds, separable)?;
+    //
+    Ok((stages, includes.into_files()))
+}
+
+/// Waits for writes made by compute shaders before the accesses given by
+/// `barriers`, e.g. `gl::SHADER_STORAGE_BARRIER_BIT`.
+pub fn memory_barrier(barriers: u32) {
+    gl_backend::current().memory_barrier(barriers);
+}
+
+/// Makes SSBO writes visible to later shader storage reads and writes.
+pub fn storage_barrier() {
+    memory_barrier(gl::SHADER_STORAGE_BARRIER_BIT);
+}
+
+/// Makes image store writes visible to later image loads and texture
+/// sampling, e.g. between a compute pass and a draw that samples its result.
+pub fn image_barrier() {
+    memory_barrier(
+        gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::TEXTURE_FETCH_BARRIER_BIT,
+    );
+}
+
+impl Shader {
+    pub fn from_file(filepath: &str) -> Result<Self, ShaderError> {
+        Self::with_defines(filepath, &[])
+    }
+    //
+    /// Compiles the file with `#define name value` for every pair in
+    /// `defines`. An empty value defines the name without a value.
+    pub fn with_defines(
+        filepath: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Self, ShaderError> {
+        Self::load(filepath, defines, false)
+    }
+    //
+    /// Links the stages in the file as a separable program, which does not
+    /// need a vertex shader and is used through a `ProgramPipeline`.
+    pub fn separable_from_file(filepath: &str) -> Result<Self, ShaderError> {
+        Self::separable_with_defines(filepath, &[])
+    }
+    //
+    pub fn separable_with_defines(
+        filepath: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Self, ShaderError> {
+        Self::load(filepath, defines, true)
+    }
+    //
+    fn load(
+        filepath: &str,
+        defines: &[(&str, &str)],
+        separable: bool,
+    ) -> Result<Self, ShaderError> {
+        let defines: Vec<_> = defines
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        let (stages, files) = parse_shader(filepath, &defines, separable)?;
+        let id = create_shader(filepath, &stages, separable)?;
+        //
+        let stages = stages.iter().map(|s| s.stage).collect();
+        Ok(glcall::try_create(|| {
+            let mut shader = Self::from_program(filepath, id, stages, files);
+            shader.defines = defines;
+            shader.separable = separable;
+            shader.reflect();
+            shader
+        })?)
+    }
+    //
+    /// Creates a program from precompiled SPIR-V, one module per stage.
+    /// `name` stands in for the file path in errors. Modules loaded with
+    /// `SpirvModule::from_file` are reloaded when their file changes.
+    pub fn from_spirv(
+        name: &str,
+        modules: Vec<SpirvModule>,
+    ) -> Result<Self, ShaderError> {
+        let id = spirv::create_program(name, &modules)?;
+        //
+        let stages = modules.iter().map(|m| m.stage()).collect();
+        let files = modules
+            .iter()
+            .filter_map(|m| m.filepath().map(str::to_owned))
+            .collect();
+        Ok(glcall::try_create(|| {
+            let mut shader = Self::from_program(name, id, stages, files);
+            shader.spirv = modules;
+            shader.reflect();
+            shader
+        })?)
+    }
+    //
+    fn from_program(
+        filepath: &str,
+        id: u32,
+        stages: Vec<ShaderStage>,
+        files: Vec<String>,
+    ) -> Self {
+        Self {
+            filepath: filepath.to_owned(),
+            defines: Vec::new(),
+            renderer_id: id,
+            stages: stages,
+            separable: false,
+            spirv: Vec::new(),
+            reflection: Reflection::default(),
+            uniform_location_cache: HashMap::new(),
+            type_warnings: HashSet::new(),
+            block_bindings: Vec::new(),
+            modified: files_modified(&files),
+            files: files,
+            last_poll: Instant::now(),
+            reload_error: None,
+        }
+    }
+    //
+    /// Queries the active inputs of the linked program and fills the
+    /// location cache with the uniforms of the default block.
+    fn reflect(&mut self) {
+        self.reflection = Reflection::from_program(self.renderer_id);
+        self.uniform_location_cache.clear();
+        self.type_warnings.clear();
+        //
+        for uniform in self.reflection.uniforms() {
+            if uniform.block_index == -1 {
+                let name = uniform.name.trim_end_matches("[0]");
+                self.uniform_location_cache
+                    .insert(name.to_owned(), uniform.location);
+            }
+        }
+        //
+        for (block, binding, size) in &self.block_bindings {
+            let result = self
+                .reflection
+                .uniform_block(block)
+                .ok_or_else(|| UniformBlockError::MissingBlock {
+                    block: block.clone(),
+                })
+                .and_then(|info| {
+                    check_block_size(block, info.data_size as usize, *size)
+                        .map(|_| info.index)
+                });
+            match result {
+                Ok(index) => {
+                    gl_backend::current().uniform_block_binding(
+                        self.renderer_id,
+                        index,
+                        *binding,
+                    );
+                    self.reflection.set_block_binding(index, *binding);
+                }
+                Err(e) => println!("Warning: {}", e),
+            }
+        }
+    }
+    //
+    /// Points the uniform block `block` at `binding`, after checking that a
+    /// `T` fits it. The binding is restored when the shader is reloaded.
+    pub fn bind_uniform_block<T: Std140>(
+        &mut self,
+        block: &str,
+        binding: u32,
+    ) -> Result<(), UniformBlockError> {
+        let info = self.reflection.uniform_block(block).ok_or_else(|| {
+            UniformBlockError::MissingBlock {
+                block: block.to_owned(),
+            }
+        })?;
+        check_block_size(block, info.data_size as usize, T::SIZE)?;
+        //
+        let index = info.index;
+        gl_backend::current().uniform_block_binding(
+            self.renderer_id,
+            index,
+            binding,
+        );
+        self.reflection.set_block_binding(index, binding);
+        //
+        self.block_bindings.retain(|(name, _, _)| name != block);
+        self.block_bindings
+            .push((block.to_owned(), binding, T::SIZE));
+        Ok(())
+    }
+    //
+    pub fn reflection(&self) -> &Reflection {
+        &self.reflection
+    }
+    //
+    /// See `Reflection::check_layout`.
+    pub fn check_layout(
+        &self,
+        layout: &VertexBufferLayout,
+    ) -> Vec<LayoutMismatch> {
+        self.reflection.check_layout(layout)
+    }
+    //
+    /// Loads a file that must contain only a `#shader compute` section.
+    pub fn compute_from_file(filepath: &str) -> Result<Self, ShaderError> {
+        let shader = Self::from_file(filepath)?;
+        if shader.is_compute() {
+            Ok(shader)
+        } else {
+            Err(ShaderError::Stages {
+                filepath: filepath.to_owned(),
+                message: "expected a compute shader".to_owned(),
+            })
+        }
+    }
+    //
+    pub fn filepath(&self) -> &str {
+        &self.filepath
+    }
+    //
+    /// The `.shader` file followed by every file it includes.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+    //
+    pub fn stages(&self) -> &[ShaderStage] {
+        &self.stages
+    }
+    //
+    pub fn is_compute(&self) -> bool {
+        self.stages == [ShaderStage::Compute]
+    }
+    //
+    pub fn is_separable(&self) -> bool {
+        self.separable
+    }
+    //
+    /// Binds the compute program and launches `x * y * z` work groups.
+    /// Results are only visible to later reads after a `memory_barrier`.
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) {
+        assert!(
+            self.is_compute(),
+            "'{}' is not a compute shader",
+            self.filepath
+        );
+        let backend = gl_backend::current();
+        backend.use_program(self.renderer_id);
+        backend.dispatch_compute(x, y, z);
+    }
+    //
+    /// Re-parses, recompiles and relinks the file. On failure the previous
+    /// program stays in use.
+    pub fn reload(&mut self) -> Result<(), ShaderError> {
+        if !self.spirv.is_empty() {
+            return self.reload_spirv();
+        }
+        //
+        let (stages, files) =
+            parse_shader(&self.filepath, &self.defines, self.separable)?;
+        let id = create_shader(&self.filepath, &stages, self.separable)?;
+        //
+        gl_backend::current().delete_program(self.renderer_id);
+        self.renderer_id = id;
+        self.stages = stages.iter().map(|s| s.stage).collect();
+        self.files = files;
+        self.reflect();
+        //
+        Ok(())
+    }
+    //
+    /// Re-reads the SPIR-V modules that came from files and relinks.
+    fn reload_spirv(&mut self) -> Result<(), ShaderError> {
+        let mut modules = self.spirv.clone();
+        for module in &mut modules {
+            module.reload()?;
+        }
+        let id = spirv::create_program(&self.filepath, &modules)?;
+        //
+        gl_backend::current().delete_program(self.renderer_id);
+        self.renderer_id = id;
+        self.spirv = modules;
+        self.reflect();
+        //
+        Ok(())
+    }
+    //
+    /// Polls the modification times of the file and its includes and
+    /// reloads when one changed.
+    /// Returns whether a new program was swapped in. A failed reload is kept
+    /// in `reload_error` until the next successful one.
+    pub fn reload_if_changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < RELOAD_POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        //
+        let modified = files_modified(&self.files);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        //
+        match self.reload() {
+            Ok(()) => {
+                println!("Reloaded shader '{}'", self.filepath);
+                self.reload_error = None;
+                true
+            }
+            Err(e) => {
+                println!("{}", e);
+                self.reload_error = Some(e);
+                false
+            }
+        }
+    }
+    //
+    pub fn reload_error(&self) -> Option<&ShaderError> {
+        self.reload_error.as_ref()
+    }
+    //
+    pub fn bind(&self) {
+        gl_backend::current().use_program(self.renderer_id);
+    }
+    //
+    pub fn unbind(&self) {
+        gl_backend::current().use_program(0);
+    }
+    //
+    fn get_uniform_location(&mut self, name: &str) -> i32 {
+        let key = name.trim_end_matches('\0');
+        if let Some(&location) = self.uniform_location_cache.get(key) {
+            location
+        } else {
+            let location = if key.contains('\0') {
+                println!("Warning: uniform: {:?} contains a NUL byte!", key);
+                -1
+            } else {
+                let location = gl_backend::current()
+                    .get_uniform_location(self.renderer_id, name);
+                if location == -1 {
+                    println!("Warning: uniform: '{}', does not exist!", key);
+                }
+                location
+            };
+            //
+            self.uniform_location_cache.insert(key.to_owned(), location);
+            location
+        }
+    }
+    //
+    /// Sets the uniform `name` to `value`, which may be any
+    /// `UniformElement` or an array or slice of them. Nothing is set, with a
+    /// warning once per name, if the reflected type does not match or the
+    /// array is too short.
+    pub fn set<U: Uniform + ?Sized>(&mut self, name: &str, value: &U) {
+        let key = name.trim_end_matches('\0');
+        if let Some(uniform) = self.reflection.uniform(key) {
+            let problem = if !value.accepts(uniform.gl_type) {
+                Some(format!(
+                    "is a {}, not a {}",
+                    type_name(uniform.gl_type),
+                    value.glsl_type()
+                ))
+            } else if value.count() > uniform.array_size as usize {
+                Some(format!(
+                    "has {} elements, not {}",
+                    uniform.array_size,
+                    value.count()
+                ))
+            } else {
+                None
+            };
+            //
+            if let Some(problem) = problem {
+                if self.type_warnings.insert(key.to_owned()) {
+                    println!("Warning: uniform: '{}' {}!", key, problem);
+                }
+                return;
+            }
+        }
+        //
+        let location = self.get_uniform_location(key);
+        value.upload(location);
+    }
+}
+
+/// Something `Renderer::draw` can bind to run the programmable stages: a
+/// `Shader` or a `ProgramPipeline`.
+pub trait ShaderProgram {
+    fn bind(&self);
+}
+
+impl ShaderProgram for Shader {
+    fn bind(&self) {
+        Shader::bind(self);
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        gl_backend::current().delete_program(self.renderer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{
+        call_log, install_mock, COLOR_SHADER,
+    };
+    use super::super::{glm, GlError};
+
+    #[test]
+    fn shader_reports_gl_error() {
+        let mock = install_mock();
+        mock.fail_on("LinkProgram", GlError::InvalidOperation);
+        match Shader::from_file(COLOR_SHADER) {
+            Err(ShaderError::Gl(error)) => {
+                assert_eq!(error.errors, [GlError::InvalidOperation])
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("the failed LinkProgram was not reported"),
+        }
+        assert_eq!(call_log(&mock).last().unwrap(), "DeleteProgram(3)");
+    }
+
+    #[test]
+    fn shader_calls() {
+        let mock = install_mock();
+        let shader = Shader::from_file(COLOR_SHADER).unwrap();
+        shader.bind();
+        drop(shader);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "CreateShader(0x8b31 -> 1)",
+                "ShaderSource(1, 106 bytes)",
+                "CompileShader(1)",
+                "GetShaderiv(1, 0x8b81)",
+                "CreateShader(0x8b30 -> 2)",
+                "ShaderSource(2, 118 bytes)",
+                "CompileShader(2)",
+                "GetShaderiv(2, 0x8b81)",
+                "CreateProgram(-> 3)",
+                "AttachShader(3, 1)",
+                "AttachShader(3, 2)",
+                "LinkProgram(3)",
+                "DeleteShader(1)",
+                "DeleteShader(2)",
+                "GetProgramiv(3, 0x8b82)",
+                "ValidateProgram(3)",
+                "GetProgramiv(3, 0x8b83)",
+                "GetProgramiv(3, 0x8b86)",
+                "GetProgramiv(3, 0x8a36)",
+                "GetProgramiv(3, 0x8b89)",
+                "UseProgram(3)",
+                "DeleteProgram(3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_validation_keeps_program() {
+        let mock = install_mock();
+        mock.set_query_result(gl::VALIDATE_STATUS, 0);
+        let shader = Shader::from_file(COLOR_SHADER).unwrap();
+        //
+        let names = mock.call_names();
+        assert!(names.contains(&"GetProgramInfoLog"));
+        assert!(!names.contains(&"DeleteProgram"));
+        drop(shader);
+    }
+
+    #[test]
+    fn uniform_name_with_nul_is_not_looked_up() {
+        let mock = install_mock();
+        let mut shader = Shader::from_file(COLOR_SHADER).unwrap();
+        mock.clear_calls();
+        shader.set("u_co\0lor", &glm::Vec4::new(1.0, 0.0, 0.0, 1.0));
+        //
+        assert_eq!(call_log(&mock), ["Uniform4fv(-1, 1, 1, 0, 0, 1)"]);
+    }
+
+    #[test]
+    fn uniforms_upload_with_their_glsl_shape() {
+        let mock = install_mock();
+        let mut shader = Shader::from_file(COLOR_SHADER).unwrap();
+        mock.clear_calls();
+        // glm's 2x3 is 2 rows by 3 columns, which is a GLSL mat3x2
+        let m = glm::Mat2x3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0);
+        shader.set("u_\0m", &m);
+        shader.set("u_\0b", &glm::BVec2::new(true, false));
+        shader.set("u_\0d", &[glm::DVec2::new(0.5, 1.5); 2]);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "UniformMatrix3x2fv(-1, 1, 1, 4, 2, 5, 3, 6)",
+                "Uniform2iv(-1, 1, 1, 0)",
+                "Uniform2dv(-1, 2, 0.5, 1.5, 0.5, 1.5)",
+            ]
+        );
+    }
+}
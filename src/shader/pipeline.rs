@@ -0,0 +1,119 @@
+use super::super::gl_backend;
+
+use super::{Shader, ShaderError, ShaderProgram, ShaderStage};
+
+/// Combines separable programs stage by stage, so one vertex program can be
+/// paired with different fragment programs without relinking anything.
+///
+/// Uniforms are still set through each stage's `Shader`, which has to be
+/// bound with `Shader::bind` while setting them. Drawing with the pipeline
+/// binds it again.
+pub struct ProgramPipeline {
+    renderer_id: u32,
+    /// The stages currently in use and the file each program came from.
+    stages: Vec<(ShaderStage, String)>,
+}
+
+impl ProgramPipeline {
+    /// Fails unless the context is GL 4.1 or has
+    /// `GL_ARB_separate_shader_objects`.
+    pub fn new() -> Result<Self, ShaderError> {
+        if !Self::is_supported() {
+            return Err(ShaderError::Unsupported {
+                feature: "GL_ARB_separate_shader_objects",
+            });
+        }
+        Ok(Self {
+            renderer_id: gl_backend::current().gen_program_pipeline(),
+            stages: Vec::new(),
+        })
+    }
+    //
+    pub fn is_supported() -> bool {
+        gl_backend::gl_version() >= (4, 1)
+            || gl_backend::has_extension("GL_ARB_separate_shader_objects")
+    }
+    //
+    /// Uses `shader` for every stage it contains, replacing whatever
+    /// program those stages had. Has to be called again after the shader
+    /// reloads, since that links a new program.
+    pub fn use_stages(&mut self, shader: &Shader) {
+        assert!(
+            shader.is_separable(),
+            "'{}' was not linked as a separable program",
+            shader.filepath()
+        );
+        let bits = shader
+            .stages()
+            .iter()
+            .fold(0, |bits, stage| bits | stage.gl_bit());
+        gl_backend::current().use_program_stages(
+            self.renderer_id,
+            bits,
+            shader.renderer_id,
+        );
+        //
+        self.stages
+            .retain(|(stage, _)| !shader.stages().contains(stage));
+        for &stage in shader.stages() {
+            self.stages.push((stage, shader.filepath().to_owned()));
+        }
+    }
+    //
+    /// Detaches whatever program `stage` has.
+    pub fn clear_stage(&mut self, stage: ShaderStage) {
+        gl_backend::current().use_program_stages(
+            self.renderer_id,
+            stage.gl_bit(),
+            0,
+        );
+        self.stages.retain(|(s, _)| *s != stage);
+    }
+    //
+    pub fn stages(&self) -> impl Iterator<Item = (ShaderStage, &str)> {
+        self.stages.iter().map(|(stage, file)| (*stage, &file[..]))
+    }
+    //
+    /// Checks the stages fit together and can run in the current state,
+    /// e.g. that the vertex outputs match the fragment inputs.
+    pub fn validate(&self) -> Result<(), ShaderError> {
+        let backend = gl_backend::current();
+        backend.validate_program_pipeline(self.renderer_id);
+        if backend
+            .get_program_pipeline_iv(self.renderer_id, gl::VALIDATE_STATUS)
+            == 0
+        {
+            let files: Vec<_> =
+                self.stages.iter().map(|(_, file)| &file[..]).collect();
+            return Err(ShaderError::Validate {
+                filepath: files.join(", "),
+                log: backend.get_program_pipeline_info_log(self.renderer_id),
+            });
+        }
+        Ok(())
+    }
+    //
+    /// A program bound with `glUseProgram` takes precedence over the
+    /// pipeline, so that is cleared first.
+    pub fn bind(&self) {
+        let backend = gl_backend::current();
+        backend.use_program(0);
+        backend.bind_program_pipeline(self.renderer_id);
+    }
+    //
+    pub fn unbind(&self) {
+        gl_backend::current().bind_program_pipeline(0);
+    }
+}
+
+impl ShaderProgram for ProgramPipeline {
+    fn bind(&self) {
+        ProgramPipeline::bind(self);
+    }
+}
+
+impl Drop for ProgramPipeline {
+    fn drop(&mut self) {
+        gl_backend::current().delete_program_pipeline(self.renderer_id);
+    }
+}
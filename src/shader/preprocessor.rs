@@ -0,0 +1,446 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use super::ShaderError;
+
+/// Where a line of preprocessed source came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Preprocessed source of one stage, with the origin of every line so
+/// driver line numbers can be mapped back to the file that was edited.
+#[derive(Default)]
+pub(super) struct MappedSource {
+    lines: Vec<String>,
+    locations: Vec<SourceLocation>,
+}
+
+impl MappedSource {
+    pub fn push(&mut self, line: &str, location: SourceLocation) {
+        self.lines.push(line.to_owned());
+        self.locations.push(location);
+    }
+    //
+    pub fn is_blank(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+    }
+    //
+    /// Location of the 1-based `line` of the preprocessed source.
+    pub fn location(&self, line: usize) -> Option<&SourceLocation> {
+        self.locations.get(line.max(1) - 1)
+    }
+    //
+    /// Inserts `#define NAME VALUE` lines after `#version`, which has to
+    /// stay the first directive, or at the top when there is none.
+    pub fn inject_defines(&mut self, defines: &[(String, String)]) {
+        let at = self
+            .lines
+            .iter()
+            .position(|line| line.trim_start().starts_with("#version"))
+            .map_or(0, |i| i + 1);
+        //
+        for (i, (name, value)) in defines.iter().enumerate().rev() {
+            self.lines.insert(at, format!("#define {} {}", name, value));
+            self.locations.insert(
+                at,
+                SourceLocation {
+                    file: "<defines>".to_owned(),
+                    line: i + 1,
+                },
+            );
+        }
+    }
+    //
+    /// The NUL-terminated source handed to the driver.
+    pub fn text(&self) -> String {
+        let mut text = self.lines.join("\n");
+        text.push_str("\n\0");
+        text
+    }
+}
+
+/// Returns the quoted file name of an `#include "file"` line, or an error
+/// message when the directive is malformed.
+fn include_target(line: &str) -> Option<Result<&str, String>> {
+    let line = line.trim();
+    if !line.starts_with("#include") {
+        return None;
+    }
+    let rest = line["#include".len()..].trim();
+    //
+    Some(
+        if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+            Ok(&rest[1..rest.len() - 1])
+        } else {
+            Err(format!("expected #include \"file\", found '{}'", line))
+        },
+    )
+}
+
+/// The macro of a classic include guard, when the file is wrapped in
+/// `#ifndef NAME`, `#define NAME` and a final `#endif`.
+fn include_guard(source: &str) -> Option<&str> {
+    let mut directives = source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"));
+    let name = directives.next()?.strip_prefix("#ifndef")?.trim();
+    let define = directives.next()?.strip_prefix("#define")?;
+    let closed = directives.next_back()?.starts_with("#endif");
+    //
+    if define.split_whitespace().next() == Some(name) && closed {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Expands `#include` directives. Paths are relative to the including
+/// file. Files marked `#pragma once` or wrapped in an include guard are
+/// only pasted once per stage, and any other include cycle is an error.
+pub(super) struct Includes {
+    stack: Vec<PathBuf>,
+    once: HashSet<PathBuf>,
+    guards: HashSet<String>,
+    files: Vec<String>,
+}
+
+impl Includes {
+    pub fn new(filepath: &str) -> Result<Self, ShaderError> {
+        let root = std::fs::canonicalize(filepath).map_err(|error| {
+            ShaderError::Io {
+                filepath: filepath.to_owned(),
+                error: error,
+            }
+        })?;
+        //
+        Ok(Self {
+            stack: vec![root],
+            once: HashSet::new(),
+            guards: HashSet::new(),
+            files: vec![filepath.to_owned()],
+        })
+    }
+    //
+    /// Every file that was read, starting with the `.shader` file itself.
+    pub fn into_files(self) -> Vec<String> {
+        self.files
+    }
+    //
+    /// `#pragma once` and include guards only apply within one stage, since
+    /// every stage is compiled separately.
+    pub fn next_stage(&mut self) {
+        self.once.clear();
+        self.guards.clear();
+    }
+    //
+    /// Appends line `line_number` of `file` to `out`, replacing it with the
+    /// included file if it is an `#include`.
+    pub fn process_line(
+        &mut self,
+        out: &mut MappedSource,
+        file: &str,
+        line_number: usize,
+        line: &str,
+    ) -> Result<(), ShaderError> {
+        let parse_error = |message| ShaderError::Parse {
+            filepath: file.to_owned(),
+            line: line_number,
+            message: message,
+        };
+        //
+        match include_target(line) {
+            None => {
+                out.push(
+                    line,
+                    SourceLocation {
+                        file: file.to_owned(),
+                        line: line_number,
+                    },
+                );
+                Ok(())
+            }
+            Some(Err(message)) => Err(parse_error(message)),
+            Some(Ok(name)) => {
+                let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+                let path = dir.join(name);
+                let path_name = path.to_string_lossy().into_owned();
+                let io_error = |error| ShaderError::Io {
+                    filepath: path_name.clone(),
+                    error: error,
+                };
+                //
+                let canonical =
+                    std::fs::canonicalize(&path).map_err(io_error)?;
+                if self.once.contains(&canonical) {
+                    return Ok(());
+                }
+                let source =
+                    std::fs::read_to_string(&path).map_err(io_error)?;
+                // The driver would drop a second copy anyway, and skipping
+                // it lets guarded files include each other
+                if let Some(guard) = include_guard(&source) {
+                    if !self.guards.insert(guard.to_owned()) {
+                        return Ok(());
+                    }
+                }
+                if self.stack.contains(&canonical) {
+                    let mut chain: Vec<_> = self
+                        .stack
+                        .iter()
+                        .map(|p| p.to_string_lossy().into_owned())
+                        .collect();
+                    chain.push(canonical.to_string_lossy().into_owned());
+                    return Err(parse_error(format!(
+                        "include cycle: {}",
+                        chain.join(" -> ")
+                    )));
+                }
+                //
+                if !self.files.contains(&path_name) {
+                    self.files.push(path_name.clone());
+                }
+                //
+                self.stack.push(canonical.clone());
+                for (i, line) in source.lines().enumerate() {
+                    if line.trim() == "#pragma once" {
+                        self.once.insert(canonical.clone());
+                    } else if line.starts_with("#shader") {
+                        return Err(ShaderError::Parse {
+                            filepath: path_name,
+                            line: i + 1,
+                            message: "#shader is not allowed in included \
+                                      files"
+                                .to_owned(),
+                        });
+                    } else {
+                        self.process_line(out, &path_name, i + 1, line)?;
+                    }
+                }
+                self.stack.pop();
+                //
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a fresh directory for the test `name`.
+    fn temp_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "preprocessor_{}_{}",
+            std::process::id(),
+            name
+        ));
+        for (file, contents) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    /// Runs every line of `file` through `includes` into a new stage.
+    fn preprocess(
+        includes: &mut Includes,
+        file: &str,
+    ) -> Result<MappedSource, ShaderError> {
+        let mut out = MappedSource::default();
+        let source = std::fs::read_to_string(file).unwrap();
+        for (i, line) in source.lines().enumerate() {
+            includes.process_line(&mut out, file, i + 1, line)?;
+        }
+        Ok(out)
+    }
+
+    fn location(file: &Path, line: usize) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string_lossy().into_owned(),
+            line: line,
+        }
+    }
+
+    #[test]
+    fn includes_are_relative_to_including_file() {
+        let dir = temp_dir(
+            "relative",
+            &[
+                ("main.shader", "#version 330\n#include \"lib/a.glsl\"\n"),
+                ("lib/a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+                ("lib/b.glsl", "float b;\n"),
+                ("b.glsl", "float wrong_b;\n"),
+            ],
+        );
+        let root = dir.join("main.shader").to_string_lossy().into_owned();
+        let mut includes = Includes::new(&root).unwrap();
+        let out = preprocess(&mut includes, &root).unwrap();
+        //
+        assert_eq!(out.lines, ["#version 330", "float b;", "float a;"]);
+        assert_eq!(
+            includes.into_files(),
+            [
+                root.clone(),
+                dir.join("lib/a.glsl").to_string_lossy().into_owned(),
+                dir.join("lib/b.glsl").to_string_lossy().into_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn source_map_points_at_original_lines() {
+        let dir = temp_dir(
+            "source_map",
+            &[
+                (
+                    "main.shader",
+                    "#version 330\n#include \"a.glsl\"\nvoid main() {}\n",
+                ),
+                ("a.glsl", "// a\nfloat a;\n"),
+            ],
+        );
+        let root = dir.join("main.shader").to_string_lossy().into_owned();
+        let mut out =
+            preprocess(&mut Includes::new(&root).unwrap(), &root).unwrap();
+        out.inject_defines(&[("A".to_owned(), "1".to_owned())]);
+        //
+        let main = dir.join("main.shader");
+        let a = dir.join("a.glsl");
+        assert_eq!(out.location(1), Some(&location(&main, 1)));
+        assert_eq!(out.location(2).unwrap().file, "<defines>");
+        assert_eq!(out.location(3), Some(&location(&a, 1)));
+        assert_eq!(out.location(4), Some(&location(&a, 2)));
+        assert_eq!(out.location(5), Some(&location(&main, 3)));
+        assert_eq!(out.location(6), None);
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let dir = temp_dir(
+            "cycle",
+            &[
+                ("main.shader", "#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"a.glsl\"\n"),
+            ],
+        );
+        let root = dir.join("main.shader").to_string_lossy().into_owned();
+        match preprocess(&mut Includes::new(&root).unwrap(), &root) {
+            Err(ShaderError::Parse {
+                filepath,
+                line,
+                message,
+            }) => {
+                assert_eq!(filepath, dir.join("b.glsl").to_string_lossy());
+                assert_eq!(line, 1);
+                assert!(message.starts_with("include cycle: "), "{}", message);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("the cycle was not detected"),
+        }
+    }
+
+    #[test]
+    fn pragma_once_is_per_stage() {
+        let dir = temp_dir(
+            "once",
+            &[
+                ("main.shader", "#include \"a.glsl\"\n#include \"a.glsl\"\n"),
+                ("a.glsl", "#pragma once\nfloat a;\n"),
+            ],
+        );
+        let root = dir.join("main.shader").to_string_lossy().into_owned();
+        let mut includes = Includes::new(&root).unwrap();
+        //
+        let out = preprocess(&mut includes, &root).unwrap();
+        assert_eq!(out.lines, ["float a;"]);
+        includes.next_stage();
+        let out = preprocess(&mut includes, &root).unwrap();
+        assert_eq!(out.lines, ["float a;"]);
+    }
+
+    #[test]
+    fn include_guards_are_honoured() {
+        let dir = temp_dir(
+            "guards",
+            &[
+                ("main.shader", "#include \"a.glsl\"\n#include \"b.glsl\"\n"),
+                (
+                    "a.glsl",
+                    "#ifndef A\n#define A\n#include \"b.glsl\"\nfloat a;\n\
+                     #endif\n",
+                ),
+                (
+                    "b.glsl",
+                    "// b\n#ifndef B\n#define B\n#include \"a.glsl\"\n\
+                     float b;\n#endif\n",
+                ),
+            ],
+        );
+        let root = dir.join("main.shader").to_string_lossy().into_owned();
+        let out =
+            preprocess(&mut Includes::new(&root).unwrap(), &root).unwrap();
+        //
+        let code: Vec<_> =
+            out.lines.iter().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(code, ["// b", "float b;", "float a;"]);
+    }
+
+    #[test]
+    fn unguarded_files_are_not_guards() {
+        assert_eq!(include_guard("#ifndef A\n#define A\n#endif\n"), Some("A"));
+        assert_eq!(include_guard("#ifndef A\n#define B\n#endif\n"), None);
+        assert_eq!(
+            include_guard("#ifndef A\n#define A\n#endif\nfloat a;"),
+            None
+        );
+        assert_eq!(include_guard("#define A\n#ifndef A\n#endif\n"), None);
+    }
+
+    #[test]
+    fn defines_go_after_version() {
+        let mut source = MappedSource::default();
+        for (i, line) in ["// header", "#version 450", "void main() {}"]
+            .iter()
+            .enumerate()
+        {
+            source.push(line, location(Path::new("a.shader"), i + 1));
+        }
+        source.inject_defines(&[
+            ("A".to_owned(), "1".to_owned()),
+            ("B".to_owned(), "2".to_owned()),
+        ]);
+        //
+        assert_eq!(
+            source.lines,
+            [
+                "// header",
+                "#version 450",
+                "#define A 1",
+                "#define B 2",
+                "void main() {}"
+            ]
+        );
+        assert_eq!(source.location(4).unwrap().line, 2);
+        //
+        let mut source = MappedSource::default();
+        source.push("void main() {}", location(Path::new("a.shader"), 1));
+        source.inject_defines(&[("A".to_owned(), "1".to_owned())]);
+        assert_eq!(source.lines, ["#define A 1", "void main() {}"]);
+    }
+}
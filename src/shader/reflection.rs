@@ -0,0 +1,372 @@
+use super::super::{gl_backend, VertexBufferLayout};
+
+use std::fmt;
+
+/// An active uniform of the default block or of a uniform block.
+#[derive(Debug, Clone)]
+pub struct UniformInfo {
+    pub name: String,
+    pub gl_type: u32,
+    pub array_size: i32,
+    /// -1 for members of a uniform block.
+    pub location: i32,
+    /// -1 for uniforms of the default block.
+    pub block_index: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformBlockInfo {
+    pub name: String,
+    pub index: u32,
+    pub binding: u32,
+    pub data_size: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub gl_type: u32,
+    pub array_size: i32,
+    pub location: i32,
+}
+
+/// Arrays are reported as `name[0]`, but are set through `name` as well.
+fn base_name(name: &str) -> &str {
+    name.trim_end_matches("[0]")
+}
+
+/// The GLSL spelling of a uniform or attribute type.
+pub fn type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::DOUBLE => "double",
+        gl::DOUBLE_VEC2 => "dvec2",
+        gl::DOUBLE_VEC3 => "dvec3",
+        gl::DOUBLE_VEC4 => "dvec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::FLOAT_MAT2x3 => "mat2x3",
+        gl::FLOAT_MAT2x4 => "mat2x4",
+        gl::FLOAT_MAT3x2 => "mat3x2",
+        gl::FLOAT_MAT3x4 => "mat3x4",
+        gl::FLOAT_MAT4x2 => "mat4x2",
+        gl::FLOAT_MAT4x3 => "mat4x3",
+        gl::DOUBLE_MAT2 => "dmat2",
+        gl::DOUBLE_MAT3 => "dmat3",
+        gl::DOUBLE_MAT4 => "dmat4",
+        gl::DOUBLE_MAT2x3 => "dmat2x3",
+        gl::DOUBLE_MAT2x4 => "dmat2x4",
+        gl::DOUBLE_MAT3x2 => "dmat3x2",
+        gl::DOUBLE_MAT3x4 => "dmat3x4",
+        gl::DOUBLE_MAT4x2 => "dmat4x2",
+        gl::DOUBLE_MAT4x3 => "dmat4x3",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        gl::IMAGE_2D => "image2D",
+        gl::INT_IMAGE_2D => "iimage2D",
+        gl::UNSIGNED_INT_IMAGE_2D => "uimage2D",
+        _ => "unknown type",
+    }
+}
+
+/// Samplers and images are set through their texture or image unit.
+pub fn is_opaque(gl_type: u32) -> bool {
+    match gl_type {
+        gl::SAMPLER_1D
+        | gl::SAMPLER_2D
+        | gl::SAMPLER_3D
+        | gl::SAMPLER_CUBE
+        | gl::SAMPLER_2D_SHADOW
+        | gl::SAMPLER_2D_ARRAY
+        | gl::SAMPLER_2D_MULTISAMPLE
+        | gl::SAMPLER_BUFFER
+        | gl::INT_SAMPLER_2D
+        | gl::UNSIGNED_INT_SAMPLER_2D
+        | gl::IMAGE_2D
+        | gl::INT_IMAGE_2D
+        | gl::UNSIGNED_INT_IMAGE_2D => true,
+        _ => false,
+    }
+}
+
+/// Scalar type, components per location and locations used by an
+/// attribute type.
+fn attribute_shape(gl_type: u32) -> (u32, i32, i32) {
+    match gl_type {
+        gl::FLOAT => (gl::FLOAT, 1, 1),
+        gl::FLOAT_VEC2 => (gl::FLOAT, 2, 1),
+        gl::FLOAT_VEC3 => (gl::FLOAT, 3, 1),
+        gl::FLOAT_VEC4 => (gl::FLOAT, 4, 1),
+        gl::FLOAT_MAT2 => (gl::FLOAT, 2, 2),
+        gl::FLOAT_MAT3 => (gl::FLOAT, 3, 3),
+        gl::FLOAT_MAT4 => (gl::FLOAT, 4, 4),
+        gl::FLOAT_MAT2x3 => (gl::FLOAT, 3, 2),
+        gl::FLOAT_MAT2x4 => (gl::FLOAT, 4, 2),
+        gl::FLOAT_MAT3x2 => (gl::FLOAT, 2, 3),
+        gl::FLOAT_MAT3x4 => (gl::FLOAT, 4, 3),
+        gl::FLOAT_MAT4x2 => (gl::FLOAT, 2, 4),
+        gl::FLOAT_MAT4x3 => (gl::FLOAT, 3, 4),
+        gl::INT => (gl::INT, 1, 1),
+        gl::INT_VEC2 => (gl::INT, 2, 1),
+        gl::INT_VEC3 => (gl::INT, 3, 1),
+        gl::INT_VEC4 => (gl::INT, 4, 1),
+        gl::UNSIGNED_INT => (gl::UNSIGNED_INT, 1, 1),
+        gl::UNSIGNED_INT_VEC2 => (gl::UNSIGNED_INT, 2, 1),
+        gl::UNSIGNED_INT_VEC3 => (gl::UNSIGNED_INT, 3, 1),
+        gl::UNSIGNED_INT_VEC4 => (gl::UNSIGNED_INT, 4, 1),
+        other => (other, 1, 1),
+    }
+}
+
+/// A difference between a `VertexBufferLayout` and the inputs of a shader.
+/// `VertexArray::add_buffer` feeds layout element `i` to location `i`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    MissingElement {
+        attribute: String,
+        location: i32,
+    },
+    TooManyComponents {
+        attribute: String,
+        location: i32,
+        expected: i32,
+        found: i32,
+    },
+    NotFloat {
+        attribute: String,
+        location: i32,
+        gl_type: u32,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutMismatch::MissingElement {
+                attribute,
+                location,
+            } => write!(
+                f,
+                "'{}' at location {} has no layout element",
+                attribute, location
+            ),
+            LayoutMismatch::TooManyComponents {
+                attribute,
+                location,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' at location {} takes {} components, the layout has {}",
+                attribute, location, expected, found
+            ),
+            LayoutMismatch::NotFloat {
+                attribute,
+                location,
+                gl_type,
+            } => write!(
+                f,
+                "'{}' at location {} is a {}, but layouts only feed floats",
+                attribute,
+                location,
+                type_name(*gl_type)
+            ),
+        }
+    }
+}
+
+/// The active uniforms, uniform blocks and vertex inputs of a linked
+/// program.
+#[derive(Debug, Clone, Default)]
+pub struct Reflection {
+    uniforms: Vec<UniformInfo>,
+    uniform_blocks: Vec<UniformBlockInfo>,
+    attributes: Vec<AttributeInfo>,
+}
+
+impl Reflection {
+    pub(super) fn from_program(program: u32) -> Self {
+        let backend = gl_backend::current();
+        //
+        let uniform_count =
+            backend.get_program_iv(program, gl::ACTIVE_UNIFORMS);
+        let uniforms = (0..uniform_count as u32)
+            .map(|index| {
+                let (name, size, gl_type) =
+                    backend.get_active_uniform(program, index);
+                let block_index = backend.get_active_uniform_iv(
+                    program,
+                    index,
+                    gl::UNIFORM_BLOCK_INDEX,
+                );
+                let location = if block_index == -1 {
+                    backend.get_uniform_location(program, &name)
+                } else {
+                    -1
+                };
+                UniformInfo {
+                    name: name,
+                    gl_type: gl_type,
+                    array_size: size,
+                    location: location,
+                    block_index: block_index,
+                }
+            })
+            .collect();
+        //
+        let block_count =
+            backend.get_program_iv(program, gl::ACTIVE_UNIFORM_BLOCKS);
+        let uniform_blocks = (0..block_count as u32)
+            .map(|index| UniformBlockInfo {
+                name: backend.get_active_uniform_block_name(program, index),
+                index: index,
+                binding: backend.get_active_uniform_block_iv(
+                    program,
+                    index,
+                    gl::UNIFORM_BLOCK_BINDING,
+                ) as u32,
+                data_size: backend.get_active_uniform_block_iv(
+                    program,
+                    index,
+                    gl::UNIFORM_BLOCK_DATA_SIZE,
+                ),
+            })
+            .collect();
+        //
+        let attribute_count =
+            backend.get_program_iv(program, gl::ACTIVE_ATTRIBUTES);
+        let attributes = (0..attribute_count as u32)
+            .map(|index| {
+                let (name, size, gl_type) =
+                    backend.get_active_attrib(program, index);
+                AttributeInfo {
+                    location: backend.get_attrib_location(program, &name),
+                    name: name,
+                    gl_type: gl_type,
+                    array_size: size,
+                }
+            })
+            // Built-ins such as gl_VertexID have no location
+            .filter(|attribute| attribute.location >= 0)
+            .collect();
+        //
+        Self {
+            uniforms: uniforms,
+            uniform_blocks: uniform_blocks,
+            attributes: attributes,
+        }
+    }
+    //
+    pub fn uniforms(&self) -> &[UniformInfo] {
+        &self.uniforms
+    }
+    //
+    /// Looks up `name`, or the first element of the array `name`.
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms
+            .iter()
+            .find(|u| u.name == name || base_name(&u.name) == name)
+    }
+    //
+    pub fn uniform_blocks(&self) -> &[UniformBlockInfo] {
+        &self.uniform_blocks
+    }
+    //
+    pub fn uniform_block(&self, name: &str) -> Option<&UniformBlockInfo> {
+        self.uniform_blocks.iter().find(|b| b.name == name)
+    }
+    //
+    pub(super) fn set_block_binding(&mut self, index: u32, binding: u32) {
+        if let Some(block) =
+            self.uniform_blocks.iter_mut().find(|b| b.index == index)
+        {
+            block.binding = binding;
+        }
+    }
+    //
+    /// The uniforms declared inside `block`.
+    pub fn block_members<'a>(
+        &'a self,
+        block: &'a UniformBlockInfo,
+    ) -> impl Iterator<Item = &'a UniformInfo> {
+        self.uniforms
+            .iter()
+            .filter(move |u| u.block_index == block.index as i32)
+    }
+    //
+    pub fn attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+    //
+    pub fn attribute(&self, name: &str) -> Option<&AttributeInfo> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+    //
+    /// Compares `layout` with the vertex inputs, as `VertexArray::add_buffer`
+    /// would bind it. Fewer components than the attribute declares are
+    /// fine, GL fills in the rest; extra elements are ignored.
+    pub fn check_layout(
+        &self,
+        layout: &VertexBufferLayout,
+    ) -> Vec<LayoutMismatch> {
+        let elements = layout.get_elements();
+        let mut mismatches = Vec::new();
+        //
+        for attribute in &self.attributes {
+            let (scalar, components, columns) =
+                attribute_shape(attribute.gl_type);
+            if scalar != gl::FLOAT {
+                mismatches.push(LayoutMismatch::NotFloat {
+                    attribute: attribute.name.clone(),
+                    location: attribute.location,
+                    gl_type: attribute.gl_type,
+                });
+                continue;
+            }
+            //
+            let locations = columns * attribute.array_size.max(1);
+            for location in attribute.location..attribute.location + locations {
+                match elements.get(location as usize) {
+                    None => mismatches.push(LayoutMismatch::MissingElement {
+                        attribute: attribute.name.clone(),
+                        location: location,
+                    }),
+                    Some(element) if element.count > components => mismatches
+                        .push(LayoutMismatch::TooManyComponents {
+                            attribute: attribute.name.clone(),
+                            location: location,
+                            expected: components,
+                            found: element.count,
+                        }),
+                    Some(_) => {}
+                }
+            }
+        }
+        //
+        mismatches
+    }
+}
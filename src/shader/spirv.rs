@@ -0,0 +1,359 @@
+use super::super::gl_backend::{self, SHADER_BINARY_FORMAT_SPIR_V};
+
+use super::{
+    catch_gl_errors, delete_shaders, link_shaders, parse_shader,
+    validate_program, validate_stages, CompileMessage, ShaderError,
+    ShaderStage,
+};
+
+use super::preprocessor::MappedSource;
+
+/// The first word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// A value for a specialization constant, passed to the driver as its bits.
+pub trait SpecConstant {
+    fn to_bits(self) -> u32;
+}
+
+impl SpecConstant for u32 {
+    fn to_bits(self) -> u32 {
+        self
+    }
+}
+
+impl SpecConstant for i32 {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+impl SpecConstant for f32 {
+    fn to_bits(self) -> u32 {
+        f32::to_bits(self)
+    }
+}
+
+impl SpecConstant for bool {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+/// A precompiled SPIR-V module for one stage, with the entry point and
+/// specialization constants to use when it is specialized.
+#[derive(Debug, Clone)]
+pub struct SpirvModule {
+    stage: ShaderStage,
+    filepath: Option<String>,
+    binary: Vec<u8>,
+    entry_point: String,
+    constants: Vec<(u32, u32)>,
+}
+
+fn check_binary(name: &str, binary: &[u8]) -> Result<(), ShaderError> {
+    let magic = binary
+        .get(..4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+    let message = if binary.len() % 4 != 0 {
+        "length is not a multiple of 4 bytes"
+    } else if magic != Some(SPIRV_MAGIC) {
+        "missing magic number"
+    } else {
+        return Ok(());
+    };
+    Err(ShaderError::Spirv {
+        filepath: name.to_owned(),
+        message: message.to_owned(),
+    })
+}
+
+impl SpirvModule {
+    pub fn from_words(stage: ShaderStage, words: &[u32]) -> Self {
+        let binary = words.iter().flat_map(|w| w.to_le_bytes().to_vec());
+        Self {
+            stage: stage,
+            filepath: None,
+            binary: binary.collect(),
+            entry_point: "main".to_owned(),
+            constants: Vec::new(),
+        }
+    }
+    //
+    /// Reads a `.spv` file, as written by `compile_to_spirv` or glslang.
+    pub fn from_file(
+        stage: ShaderStage,
+        filepath: &str,
+    ) -> Result<Self, ShaderError> {
+        let binary =
+            std::fs::read(filepath).map_err(|error| ShaderError::Io {
+                filepath: filepath.to_owned(),
+                error: error,
+            })?;
+        check_binary(filepath, &binary)?;
+        //
+        Ok(Self {
+            stage: stage,
+            filepath: Some(filepath.to_owned()),
+            binary: binary,
+            entry_point: "main".to_owned(),
+            constants: Vec::new(),
+        })
+    }
+    //
+    /// Defaults to `main`.
+    pub fn with_entry_point(mut self, entry_point: &str) -> Self {
+        self.entry_point = entry_point.to_owned();
+        self
+    }
+    //
+    /// Sets the constant declared with `layout(constant_id = id)`.
+    pub fn with_constant<T: SpecConstant>(mut self, id: u32, value: T) -> Self {
+        self.constants.retain(|&(i, _)| i != id);
+        self.constants.push((id, value.to_bits()));
+        self
+    }
+    //
+    pub fn stage(&self) -> ShaderStage {
+        self.stage
+    }
+    //
+    pub fn filepath(&self) -> Option<&str> {
+        self.filepath.as_deref()
+    }
+    //
+    /// Re-reads the binary of a module that came from a file.
+    pub(super) fn reload(&mut self) -> Result<(), ShaderError> {
+        if let Some(filepath) = &self.filepath {
+            let reloaded = Self::from_file(self.stage, filepath)?;
+            self.binary = reloaded.binary;
+        }
+        Ok(())
+    }
+    //
+    fn compile(&self, name: &str) -> Result<u32, ShaderError> {
+        let name = self.filepath().unwrap_or(name);
+        check_binary(name, &self.binary)?;
+        if self.entry_point.contains('\0') {
+            return Err(ShaderError::Spirv {
+                filepath: name.to_owned(),
+                message: format!(
+                    "entry point {:?} contains a NUL byte",
+                    self.entry_point
+                ),
+            });
+        }
+        //
+        let backend = gl_backend::current();
+        let id = backend.create_shader(self.stage.gl_type());
+        backend.shader_binary(id, SHADER_BINARY_FORMAT_SPIR_V, &self.binary);
+        backend.specialize_shader(id, &self.entry_point, &self.constants);
+        //
+        if backend.get_shader_iv(id, gl::COMPILE_STATUS) == 0 {
+            let log = backend.get_shader_info_log(id);
+            backend.delete_shader(id);
+            return Err(ShaderError::Compile {
+                filepath: name.to_owned(),
+                stage: self.stage,
+                messages: log
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| CompileMessage {
+                        location: None,
+                        message: line.trim().to_owned(),
+                    })
+                    .collect(),
+            });
+        }
+        //
+        Ok(id)
+    }
+}
+
+/// Specializes every module and links them, like `create_shader` does for
+/// GLSL. Needs `GL_ARB_gl_spirv` or GL 4.6.
+pub(super) fn create_program(
+    name: &str,
+    modules: &[SpirvModule],
+) -> Result<u32, ShaderError> {
+    if !gl_backend::current().supports_spirv() {
+        return Err(ShaderError::Spirv {
+            filepath: name.to_owned(),
+            message: "GL_ARB_gl_spirv is not supported".to_owned(),
+        });
+    }
+    let stages: Vec<_> = modules.iter().map(|m| m.stage).collect();
+    validate_stages(name, &stages, false)?;
+    //
+    catch_gl_errors(|| {
+        let mut shaders = Vec::new();
+        for module in modules {
+            match module.compile(name) {
+                Ok(id) => shaders.push(id),
+                Err(e) => {
+                    delete_shaders(&shaders);
+                    return Err(e);
+                }
+            }
+        }
+        let program = link_shaders(name, &shaders, false, false)?;
+        validate_program(name, program);
+        Ok(program)
+    })
+}
+
+fn naga_stage(stage: ShaderStage) -> Option<naga::ShaderStage> {
+    match stage {
+        ShaderStage::Vertex => Some(naga::ShaderStage::Vertex),
+        ShaderStage::Fragment => Some(naga::ShaderStage::Fragment),
+        ShaderStage::Compute => Some(naga::ShaderStage::Compute),
+        _ => None,
+    }
+}
+
+/// Points a naga error at the file and line the preprocessed line came from.
+fn naga_message(
+    source: &MappedSource,
+    text: &str,
+    span: Option<naga::Span>,
+    message: String,
+) -> CompileMessage {
+    let line = span
+        .and_then(|span| span.to_range())
+        .and_then(|range| text.get(..range.start))
+        .map(|before| before.matches('\n').count() + 1);
+    CompileMessage {
+        location: line.and_then(|line| source.location(line).cloned()),
+        message: message,
+    }
+}
+
+fn compile_stage(
+    filepath: &str,
+    stage: ShaderStage,
+    source: &MappedSource,
+) -> Result<Vec<u32>, ShaderError> {
+    let error = |messages| ShaderError::Compile {
+        filepath: filepath.to_owned(),
+        stage: stage,
+        messages: messages,
+    };
+    let naga_stage = naga_stage(stage).ok_or_else(|| ShaderError::Spirv {
+        filepath: filepath.to_owned(),
+        message: format!("naga cannot compile {} shaders", stage.name()),
+    })?;
+    //
+    let text = source.text();
+    let text = text.trim_end_matches('\0');
+    //
+    let module = naga::front::glsl::Parser::default()
+        .parse(&naga::front::glsl::Options::from(naga_stage), text)
+        .map_err(|errors| {
+            error(
+                errors
+                    .iter()
+                    .map(|e| {
+                        naga_message(
+                            source,
+                            text,
+                            Some(e.meta),
+                            e.kind.to_string(),
+                        )
+                    })
+                    .collect(),
+            )
+        })?;
+    //
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| {
+        error(vec![naga_message(source, text, None, e.to_string())])
+    })?;
+    //
+    // GL consumes SPIR-V 1.0
+    let options = naga::back::spv::Options {
+        lang_version: (1, 0),
+        ..Default::default()
+    };
+    let pipeline = naga::back::spv::PipelineOptions {
+        shader_stage: naga_stage,
+        entry_point: "main".to_owned(),
+    };
+    naga::back::spv::write_vec(&module, &info, &options, Some(&pipeline))
+        .map_err(|e| ShaderError::Spirv {
+            filepath: filepath.to_owned(),
+            message: e.to_string(),
+        })
+}
+
+/// Compiles every stage of a `.shader` file to SPIR-V without a driver.
+/// The stages have to be written for `#version 450` with explicit bindings
+/// and locations, which is what naga's GLSL front end accepts, see
+/// `res/shaders/spirv/solid.shader`. It does not
+/// support `constant_id` yet, so modules using specialization constants
+/// still need glslang.
+pub fn compile_to_spirv(
+    filepath: &str,
+    defines: &[(&str, &str)],
+) -> Result<Vec<(ShaderStage, Vec<u32>)>, ShaderError> {
+    let defines: Vec<_> = defines
+        .iter()
+        .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    let (stages, _) = parse_shader(filepath, &defines, false)?;
+    //
+    stages
+        .iter()
+        .map(|s| Ok((s.stage, compile_stage(filepath, s.stage, &s.source)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::super::gl_backend::tests::install_mock;
+    use super::super::Shader;
+
+    #[test]
+    fn spirv_program_calls() {
+        let mock = install_mock();
+        let words = [0x0723_0203, 0x0001_0000, 0, 1, 0];
+        let modules = vec![
+            SpirvModule::from_words(ShaderStage::Vertex, &words)
+                .with_constant(3, 7u32),
+            SpirvModule::from_words(ShaderStage::Fragment, &words)
+                .with_entry_point("frag_main")
+                .with_constant(0, 1.0f32)
+                .with_constant(5, true),
+        ];
+        drop(Shader::from_spirv("unit", modules).unwrap());
+        //
+        let names = [
+            "ShaderBinary",
+            "SpecializeShader",
+            "LinkProgram",
+            "ValidateProgram",
+        ];
+        let log: Vec<_> = mock
+            .calls()
+            .iter()
+            .filter(|call| names.contains(&call.name))
+            .map(|call| format!("{}({})", call.name, call.args))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                "ShaderBinary(1, 0x9551, 20 bytes)",
+                "SpecializeShader(1, main, 3=0x7)",
+                "ShaderBinary(2, 0x9551, 20 bytes)",
+                "SpecializeShader(2, frag_main, 0=0x3f800000, 5=0x1)",
+                "LinkProgram(3)",
+                "ValidateProgram(3)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use super::super::gl_backend;
+
+use super::reflection::is_opaque;
+
+use nalgebra_glm as glm;
+
+/// A scalar, vector or matrix type with a GLSL counterpart. Arrays and
+/// slices of it set GLSL arrays.
+pub trait UniformElement: Copy {
+    /// The GLSL type name used in type mismatch warnings.
+    const GLSL_TYPE: &'static str;
+    /// Whether a uniform of the reflected `gl_type` can be set from `Self`.
+    fn accepts(gl_type: u32) -> bool;
+    /// Sets `values.len()` consecutive array elements from `location` on.
+    fn upload(location: i32, values: &[Self]);
+}
+
+/// Anything `Shader::set` takes: a single element or an array or slice of
+/// them.
+pub trait Uniform {
+    fn glsl_type(&self) -> &'static str;
+    fn accepts(&self, gl_type: u32) -> bool;
+    /// The number of array elements that are set.
+    fn count(&self) -> usize;
+    fn upload(&self, location: i32);
+}
+
+impl<T: UniformElement> Uniform for T {
+    fn glsl_type(&self) -> &'static str {
+        T::GLSL_TYPE
+    }
+    //
+    fn accepts(&self, gl_type: u32) -> bool {
+        T::accepts(gl_type)
+    }
+    //
+    fn count(&self) -> usize {
+        1
+    }
+    //
+    fn upload(&self, location: i32) {
+        T::upload(location, std::slice::from_ref(self));
+    }
+}
+
+impl<T: UniformElement> Uniform for [T] {
+    fn glsl_type(&self) -> &'static str {
+        T::GLSL_TYPE
+    }
+    //
+    fn accepts(&self, gl_type: u32) -> bool {
+        T::accepts(gl_type)
+    }
+    //
+    fn count(&self) -> usize {
+        self.len()
+    }
+    //
+    fn upload(&self, location: i32) {
+        T::upload(location, self);
+    }
+}
+
+impl<T: UniformElement, const N: usize> Uniform for [T; N] {
+    fn glsl_type(&self) -> &'static str {
+        T::GLSL_TYPE
+    }
+    //
+    fn accepts(&self, gl_type: u32) -> bool {
+        T::accepts(gl_type)
+    }
+    //
+    fn count(&self) -> usize {
+        N
+    }
+    //
+    fn upload(&self, location: i32) {
+        T::upload(location, &self[..]);
+    }
+}
+
+impl UniformElement for f32 {
+    const GLSL_TYPE: &'static str = "float";
+    //
+    fn accepts(gl_type: u32) -> bool {
+        gl_type == gl::FLOAT
+    }
+    //
+    fn upload(location: i32, values: &[Self]) {
+        gl_backend::current().uniform_fv(location, 1, values);
+    }
+}
+
+/// Also sets samplers and images to a texture or image unit.
+impl UniformElement for i32 {
+    const GLSL_TYPE: &'static str = "int";
+    //
+    fn accepts(gl_type: u32) -> bool {
+        gl_type == gl::INT || gl_type == gl::BOOL || is_opaque(gl_type)
+    }
+    //
+    fn upload(location: i32, values: &[Self]) {
+        gl_backend::current().uniform_iv(location, 1, values);
+    }
+}
+
+impl UniformElement for u32 {
+    const GLSL_TYPE: &'static str = "uint";
+    //
+    fn accepts(gl_type: u32) -> bool {
+        gl_type == gl::UNSIGNED_INT || gl_type == gl::BOOL
+    }
+    //
+    fn upload(location: i32, values: &[Self]) {
+        gl_backend::current().uniform_uiv(location, 1, values);
+    }
+}
+
+impl UniformElement for bool {
+    const GLSL_TYPE: &'static str = "bool";
+    //
+    fn accepts(gl_type: u32) -> bool {
+        gl_type == gl::BOOL
+    }
+    //
+    fn upload(location: i32, values: &[Self]) {
+        let values: Vec<i32> = values.iter().map(|&v| v as i32).collect();
+        gl_backend::current().uniform_iv(location, 1, &values);
+    }
+}
+
+impl UniformElement for f64 {
+    const GLSL_TYPE: &'static str = "double";
+    //
+    fn accepts(gl_type: u32) -> bool {
+        gl_type == gl::DOUBLE
+    }
+    //
+    fn upload(location: i32, values: &[Self]) {
+        gl_backend::current().uniform_dv(location, 1, values);
+    }
+}
+
+/// Implements `UniformElement` for a glm vector or matrix type by
+/// flattening it into its column-major components, converted to `$scalar`.
+macro_rules! uniform_element {
+    (
+        $ty:ty,
+        $scalar:ty,
+        $glsl:expr,
+        $gl_type:expr,
+        $upload:ident($($size:expr),*)
+    ) => {
+        impl UniformElement for $ty {
+            const GLSL_TYPE: &'static str = $glsl;
+            //
+            fn accepts(gl_type: u32) -> bool {
+                gl_type == $gl_type
+            }
+            //
+            fn upload(location: i32, values: &[Self]) {
+                let values: Vec<$scalar> = values
+                    .iter()
+                    .flat_map(|v| v.as_slice().iter().copied())
+                    .map(<$scalar>::from)
+                    .collect();
+                gl_backend::current().$upload(location, $($size,)* &values);
+            }
+        }
+    };
+}
+
+uniform_element!(glm::Vec2, f32, "vec2", gl::FLOAT_VEC2, uniform_fv(2));
+uniform_element!(glm::Vec3, f32, "vec3", gl::FLOAT_VEC3, uniform_fv(3));
+uniform_element!(glm::Vec4, f32, "vec4", gl::FLOAT_VEC4, uniform_fv(4));
+uniform_element!(glm::IVec2, i32, "ivec2", gl::INT_VEC2, uniform_iv(2));
+uniform_element!(glm::IVec3, i32, "ivec3", gl::INT_VEC3, uniform_iv(3));
+uniform_element!(glm::IVec4, i32, "ivec4", gl::INT_VEC4, uniform_iv(4));
+uniform_element!(
+    glm::UVec2,
+    u32,
+    "uvec2",
+    gl::UNSIGNED_INT_VEC2,
+    uniform_uiv(2)
+);
+uniform_element!(
+    glm::UVec3,
+    u32,
+    "uvec3",
+    gl::UNSIGNED_INT_VEC3,
+    uniform_uiv(3)
+);
+uniform_element!(
+    glm::UVec4,
+    u32,
+    "uvec4",
+    gl::UNSIGNED_INT_VEC4,
+    uniform_uiv(4)
+);
+uniform_element!(glm::BVec2, i32, "bvec2", gl::BOOL_VEC2, uniform_iv(2));
+uniform_element!(glm::BVec3, i32, "bvec3", gl::BOOL_VEC3, uniform_iv(3));
+uniform_element!(glm::BVec4, i32, "bvec4", gl::BOOL_VEC4, uniform_iv(4));
+uniform_element!(glm::DVec2, f64, "dvec2", gl::DOUBLE_VEC2, uniform_dv(2));
+uniform_element!(glm::DVec3, f64, "dvec3", gl::DOUBLE_VEC3, uniform_dv(3));
+uniform_element!(glm::DVec4, f64, "dvec4", gl::DOUBLE_VEC4, uniform_dv(4));
+uniform_element!(
+    glm::Mat2,
+    f32,
+    "mat2",
+    gl::FLOAT_MAT2,
+    uniform_matrix_fv(2, 2)
+);
+uniform_element!(
+    glm::Mat3,
+    f32,
+    "mat3",
+    gl::FLOAT_MAT3,
+    uniform_matrix_fv(3, 3)
+);
+uniform_element!(
+    glm::Mat4,
+    f32,
+    "mat4",
+    gl::FLOAT_MAT4,
+    uniform_matrix_fv(4, 4)
+);
+uniform_element!(
+    glm::Mat2x3,
+    f32,
+    "mat3x2",
+    gl::FLOAT_MAT3x2,
+    uniform_matrix_fv(3, 2)
+);
+uniform_element!(
+    glm::Mat2x4,
+    f32,
+    "mat4x2",
+    gl::FLOAT_MAT4x2,
+    uniform_matrix_fv(4, 2)
+);
+uniform_element!(
+    glm::Mat3x2,
+    f32,
+    "mat2x3",
+    gl::FLOAT_MAT2x3,
+    uniform_matrix_fv(2, 3)
+);
+uniform_element!(
+    glm::Mat3x4,
+    f32,
+    "mat4x3",
+    gl::FLOAT_MAT4x3,
+    uniform_matrix_fv(4, 3)
+);
+uniform_element!(
+    glm::Mat4x2,
+    f32,
+    "mat2x4",
+    gl::FLOAT_MAT2x4,
+    uniform_matrix_fv(2, 4)
+);
+uniform_element!(
+    glm::Mat4x3,
+    f32,
+    "mat3x4",
+    gl::FLOAT_MAT3x4,
+    uniform_matrix_fv(3, 4)
+);
+uniform_element!(
+    glm::DMat2,
+    f64,
+    "dmat2",
+    gl::DOUBLE_MAT2,
+    uniform_matrix_dv(2, 2)
+);
+uniform_element!(
+    glm::DMat3,
+    f64,
+    "dmat3",
+    gl::DOUBLE_MAT3,
+    uniform_matrix_dv(3, 3)
+);
+uniform_element!(
+    glm::DMat4,
+    f64,
+    "dmat4",
+    gl::DOUBLE_MAT4,
+    uniform_matrix_dv(4, 4)
+);
+uniform_element!(
+    glm::DMat2x3,
+    f64,
+    "dmat3x2",
+    gl::DOUBLE_MAT3x2,
+    uniform_matrix_dv(3, 2)
+);
+uniform_element!(
+    glm::DMat2x4,
+    f64,
+    "dmat4x2",
+    gl::DOUBLE_MAT4x2,
+    uniform_matrix_dv(4, 2)
+);
+uniform_element!(
+    glm::DMat3x2,
+    f64,
+    "dmat2x3",
+    gl::DOUBLE_MAT2x3,
+    uniform_matrix_dv(2, 3)
+);
+uniform_element!(
+    glm::DMat3x4,
+    f64,
+    "dmat4x3",
+    gl::DOUBLE_MAT4x3,
+    uniform_matrix_dv(4, 3)
+);
+uniform_element!(
+    glm::DMat4x2,
+    f64,
+    "dmat2x4",
+    gl::DOUBLE_MAT2x4,
+    uniform_matrix_dv(2, 4)
+);
+uniform_element!(
+    glm::DMat4x3,
+    f64,
+    "dmat3x4",
+    gl::DOUBLE_MAT3x4,
+    uniform_matrix_dv(3, 4)
+);
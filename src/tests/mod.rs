@@ -1,7 +1,50 @@
 pub mod test_clear_color;
+pub mod test_pipeline;
 pub mod test_texture2d;
 
-use imgui_glfw_rs::imgui::{im_str, Window};
+use imgui_glfw_rs::imgui::{im_str, Condition, Window};
+
+use nalgebra_glm as glm;
+
+use super::{
+    frame_clock::FrameClock, recorder::Recorder, screenshot, Shader,
+    ShaderCache, UniformBuffer,
+};
+
+use std::{cell::RefCell, rc::Rc};
+
+/// The binding point of the `Camera` block the test menu keeps up to date.
+pub const CAMERA_BINDING: u32 = 0;
+
+std140_struct! {
+    /// Matches `Camera` in `res/shaders/common/transform.glsl`.
+    pub struct Camera {
+        pub proj: glm::Mat4,
+        pub view: glm::Mat4,
+    }
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self {
+            proj: glm::ortho(-2.0, 2.0, -1.5, 1.5, -1.0, 1.0),
+            view: glm::identity(),
+        }
+    }
+}
+
+thread_local! {
+    static SHADER_CACHE: Rc<RefCell<ShaderCache>> =
+        Rc::new(RefCell::new(ShaderCache::new()));
+}
+
+/// The cache every test compiles its shader variants through. It outlives
+/// the tests, so leaving and re-entering one does not recompile anything.
+/// The `TestMenu` empties it when dropped, while the context is still
+/// current.
+pub fn shader_cache() -> Rc<RefCell<ShaderCache>> {
+    SHADER_CACHE.with(Rc::clone)
+}
 
 pub trait OGLTest {
     fn new() -> Self
@@ -12,15 +55,167 @@ pub trait OGLTest {
     fn on_imgui_render(&mut self, _ui: &imgui_glfw_rs::imgui::Ui) {}
 }
 
+/// Shows the error of the last failed hot reload of `shader`, if any, in a
+/// window of its own.
+pub fn shader_error_overlay(ui: &imgui_glfw_rs::imgui::Ui, shader: &Shader) {
+    if let Some(error) = shader.reload_error() {
+        Window::new(ui, &im_str!("Shader error")).build(|| {
+            ui.text_colored([1.0, 0.3, 0.3, 1.0], format!("{}", error));
+        });
+    }
+}
+
+/// Lists the compiled variants of `cache` with its hit and miss counts.
+pub fn shader_cache_stats(ui: &imgui_glfw_rs::imgui::Ui, cache: &ShaderCache) {
+    let stats = cache.stats();
+    ui.separator();
+    ui.text(format!(
+        "Shader variants: {} ({} hits, {} misses, {} failed)",
+        cache.len(),
+        stats.hits,
+        stats.misses,
+        stats.failures
+    ));
+    ui.text(format!(
+        "Compile time: {:.1} ms",
+        stats.compile_time.as_secs_f32() * 1000.0
+    ));
+    for (key, _) in cache.variants() {
+        ui.text(format!("  {} [{}]", key.filepath, key.keywords.join(", ")));
+    }
+}
+
 pub struct TestMenu {
     current_test: Option<(String, Box<dyn OGLTest>)>,
     tests: Vec<(String, fn() -> Box<dyn OGLTest>)>,
+    //
+    clock: FrameClock,
+    camera: UniformBuffer<Camera>,
+    //
+    recorder: Recorder,
+    record_gif: bool,
+    record_every: i32,
+}
+
+fn recording_path(test_name: &str, gif: bool) -> String {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let name: String = test_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!(
+        "recordings/{}_{}{}",
+        name,
+        millis,
+        if gif { ".gif" } else { "" }
+    )
+}
+
+fn clock_controls(ui: &imgui_glfw_rs::imgui::Ui, clock: &mut FrameClock) {
+    let frame_delta = clock.frame_delta();
+    ui.text(format!(
+        "{:.2} ms ({:.0} fps)",
+        frame_delta * 1000.0,
+        if frame_delta > 0.0 {
+            1.0 / frame_delta
+        } else {
+            0.0
+        }
+    ));
+    ui.checkbox(im_str!("Pause"), &mut clock.paused);
+    ui.slider_float(im_str!("Time scale"), &mut clock.time_scale, 0.0, 4.0)
+        .build();
+    //
+    let mut fixed_step = clock.fixed_step().is_some();
+    if ui.checkbox(im_str!("Fixed 60 Hz step"), &mut fixed_step) {
+        clock.set_fixed_step(if fixed_step { Some(1.0 / 60.0) } else { None });
+    }
+}
+
+/// Shows start/stop controls for `recorder` below the active test.
+fn recorder_controls(
+    ui: &imgui_glfw_rs::imgui::Ui,
+    frame_delta: f32,
+    recorder: &mut Recorder,
+    record_gif: &mut bool,
+    record_every: &mut i32,
+    test_name: &str,
+) {
+    ui.separator();
+    if recorder.is_recording() {
+        ui.text(format!("Recording: {} frames", recorder.captured()));
+        if ui.button(im_str!("Stop recording"), [0.0, 0.0]) {
+            let frames = recorder.stop();
+            println!("Recorded {} frames of '{}'", frames, test_name);
+        }
+    } else {
+        ui.checkbox(im_str!("GIF"), record_gif);
+        ui.input_int(im_str!("Every Nth frame"), record_every)
+            .build();
+        *record_every = (*record_every).max(1);
+        //
+        if ui.button(im_str!("Start recording"), [0.0, 0.0]) {
+            let every = *record_every as u32;
+            let path = recording_path(test_name, *record_gif);
+            let result = if *record_gif {
+                let delay_ms = (frame_delta * 1000.0 * every as f32) as u32;
+                recorder.start_gif(&path, every, delay_ms)
+            } else {
+                recorder.start_png_sequence(&path, every)
+            };
+            match result {
+                Ok(()) => println!("Recording to {}", path),
+                Err(e) => println!("Failed to start recording: {}", e),
+            }
+        }
+    }
 }
 
 impl TestMenu {
     pub fn register_test<T: OGLTest + 'static>(&mut self, name: &str) {
         self.tests.push((name.to_owned(), || Box::new(T::new())))
     }
+    //
+    pub fn test_names(&self) -> Vec<&str> {
+        self.tests.iter().map(|(name, _)| &name[..]).collect()
+    }
+    //
+    pub fn set_clock(&mut self, clock: FrameClock) {
+        self.clock = clock;
+    }
+    //
+    /// Advances the frame clock and updates the current test with the
+    /// resulting deltas.
+    pub fn tick(&mut self) {
+        for delta_time in self.clock.tick() {
+            self.on_update(delta_time);
+        }
+    }
+    //
+    /// Changes the projection and view every test shader sees through the
+    /// `Camera` block.
+    pub fn set_camera(&self, camera: &Camera) {
+        self.camera.update(camera);
+    }
+    //
+    pub fn recorder_mut(&mut self) -> &mut Recorder {
+        &mut self.recorder
+    }
+    //
+    /// Starts the test registered as `name`, as if its button was pressed.
+    pub fn select_test(&mut self, name: &str) -> bool {
+        if let Some((test_name, test_constructor)) =
+            self.tests.iter().find(|(test_name, _)| test_name == name)
+        {
+            self.current_test = Some((test_name.clone(), test_constructor()));
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl OGLTest for TestMenu {
@@ -28,6 +223,12 @@ impl OGLTest for TestMenu {
         Self {
             current_test: None,
             tests: Vec::new(),
+            clock: FrameClock::new(),
+            camera: UniformBuffer::with_binding(CAMERA_BINDING, &Camera::new())
+                .unwrap_or_else(|e| panic!("{}", e)),
+            recorder: Recorder::new(),
+            record_gif: true,
+            record_every: 1,
         }
     }
     //
@@ -40,25 +241,60 @@ impl OGLTest for TestMenu {
     fn on_render(&mut self) {
         if let Some((_, test)) = &mut self.current_test {
             test.on_render();
+            //
+            let result =
+                self.recorder.on_frame(screenshot::read_current_framebuffer);
+            if let Err(e) = result {
+                println!("Recording failed: {}", e);
+                self.recorder.stop();
+            }
         }
     }
     //
     fn on_imgui_render(&mut self, ui: &imgui_glfw_rs::imgui::Ui) {
+        // Drawn on its own, in the top right corner away from the test
+        // windows, so it stays reachable while a test is running
+        let clock = &mut self.clock;
+        let [width, _] = ui.io().display_size;
+        Window::new(ui, im_str!("Frame Clock"))
+            .position([width - 10.0, 10.0], Condition::FirstUseEver)
+            .position_pivot([1.0, 0.0])
+            .always_auto_resize(true)
+            .build(|| {
+                clock_controls(ui, clock);
+            });
+        //
         if let Some((test_name, test)) = &mut self.current_test {
+            let frame_delta = self.clock.frame_delta();
+            let recorder = &mut self.recorder;
+            let record_gif = &mut self.record_gif;
+            let record_every = &mut self.record_every;
+            //
             let mut close = false;
             Window::new(ui, &im_str!("{}", test_name)).build(|| {
                 close = ui.button(&im_str!("<-"), [0.0, 0.0]);
                 test.on_imgui_render(ui);
+                recorder_controls(
+                    ui,
+                    frame_delta,
+                    recorder,
+                    record_gif,
+                    record_every,
+                    test_name,
+                );
             });
             //
             if close {
+                self.recorder.stop();
                 self.current_test = None;
             }
         } else {
+            let tests = &self.tests;
+            let current_test = &mut self.current_test;
             Window::new(ui, &im_str!("Test Menu")).build(|| {
-                for (test_name, test_constructor) in &self.tests {
+                for (test_name, test_constructor) in tests {
                     if ui.button(&im_str!("{}", test_name), [0.0, 0.0]) {
-                        self.current_test =
+                        *current_test =
                             Some((test_name.clone(), test_constructor()));
                     }
                 }
@@ -66,3 +302,9 @@ impl OGLTest for TestMenu {
         }
     }
 }
+
+impl Drop for TestMenu {
+    fn drop(&mut self) {
+        shader_cache().borrow_mut().clear();
+    }
+}
@@ -0,0 +1,130 @@
+use super::super::*;
+
+use super::{shader_error_overlay, Camera, CAMERA_BINDING};
+
+use std::convert::TryFrom;
+
+const VERTEX: &str = "res/shaders/pipeline/quad.shader";
+const FRAGMENTS: [&str; 2] = [
+    "res/shaders/pipeline/gradient.shader",
+    "res/shaders/pipeline/checker.shader",
+];
+
+fn load_separable(filepath: &str) -> Shader {
+    Shader::separable_from_file(filepath).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// One vertex program shared by two fragment programs, switched by swapping
+/// the fragment stage of a pipeline instead of relinking.
+pub struct TestProgramPipeline {
+    va: VertexArray,
+    _vb: VertexBuffer<[[f32; 2]; 2]>,
+    ib: IndexBuffer,
+    //
+    vertex: Shader,
+    fragments: [Shader; 2],
+    pipeline: ProgramPipeline,
+    selected: usize,
+    cells: f32,
+    //
+    renderer: Renderer,
+}
+
+impl OGLTest for TestProgramPipeline {
+    fn new() -> Self {
+        let mut pipeline =
+            ProgramPipeline::new().unwrap_or_else(|e| panic!("{}", e));
+        //
+        let positions: &[_] = &[
+            [[-1.0f32, -1.0], [0.0, 0.0]],
+            [[1.0, -1.0], [1.0, 0.0]],
+            [[1.0, 1.0], [1.0, 1.0]],
+            [[-1.0, 1.0], [0.0, 1.0]],
+        ];
+        let indices: &[[u16; 3]] = &[[0, 1, 2], [2, 3, 0]];
+        //
+        let vb = VertexBuffer::try_from(positions)
+            .unwrap_or_else(|e| panic!("{}", e));
+        //
+        let mut layout = VertexBufferLayout::new();
+        layout.push::<f32>(2);
+        layout.push::<f32>(2);
+        //
+        let mut vertex = load_separable(VERTEX);
+        vertex
+            .bind_uniform_block::<Camera>("Camera", CAMERA_BINDING)
+            .unwrap_or_else(|e| panic!("{}", e));
+        for mismatch in vertex.check_layout(&layout) {
+            println!("Warning: {}", mismatch);
+        }
+        let fragments =
+            [load_separable(FRAGMENTS[0]), load_separable(FRAGMENTS[1])];
+        //
+        let va = VertexArray::new().unwrap_or_else(|e| panic!("{}", e));
+        va.add_buffer(&vb, layout)
+            .unwrap_or_else(|e| panic!("{}", e));
+        //
+        let ib =
+            IndexBuffer::try_from(indices).unwrap_or_else(|e| panic!("{}", e));
+        //
+        pipeline.use_stages(&vertex);
+        pipeline.use_stages(&fragments[0]);
+        if let Err(e) = pipeline.validate() {
+            println!("Warning: {}", e);
+        }
+        //
+        Self {
+            va: va,
+            _vb: vb,
+            ib: ib,
+            vertex: vertex,
+            fragments: fragments,
+            pipeline: pipeline,
+            selected: 0,
+            cells: 8.0,
+            renderer: Renderer {},
+        }
+    }
+    //
+    fn on_update(&mut self, _delta_time: f32) {
+        // A reload links a new program, which the pipeline has to pick up
+        if self.vertex.reload_if_changed() {
+            self.pipeline.use_stages(&self.vertex);
+        }
+        for (i, fragment) in self.fragments.iter_mut().enumerate() {
+            if fragment.reload_if_changed() && i == self.selected {
+                self.pipeline.use_stages(fragment);
+            }
+        }
+    }
+    //
+    fn on_render(&mut self) {
+        self.vertex.bind();
+        self.vertex.set("u_model", &glm::Mat4::identity());
+        //
+        let fragment = &mut self.fragments[self.selected];
+        if fragment.reflection().uniform("u_cells").is_some() {
+            fragment.bind();
+            fragment.set("u_cells", &self.cells);
+        }
+        //
+        self.renderer.draw(&self.va, &self.ib, &self.pipeline);
+    }
+    //
+    fn on_imgui_render(&mut self, ui: &imgui_glfw_rs::imgui::Ui) {
+        shader_error_overlay(ui, &self.vertex);
+        for fragment in &self.fragments {
+            shader_error_overlay(ui, fragment);
+        }
+        //
+        let previous = self.selected;
+        ui.radio_button(im_str!("Gradient"), &mut self.selected, 0);
+        ui.radio_button(im_str!("Checker"), &mut self.selected, 1);
+        if self.selected != previous {
+            self.pipeline.use_stages(&self.fragments[self.selected]);
+        }
+        //
+        ui.slider_float(im_str!("Cells"), &mut self.cells, 1.0, 32.0)
+            .build();
+    }
+}
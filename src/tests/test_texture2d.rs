@@ -1,25 +1,43 @@
 use super::super::*;
 
-use std::io::Write;
+use super::{
+    shader_cache, shader_cache_stats, shader_error_overlay, Camera,
+    CAMERA_BINDING,
+};
+
+use std::{cell::RefCell, convert::TryFrom, io::Write, rc::Rc, time::Instant};
+
+const SHADER: &str = "res/shaders/basic.shader";
+
+fn keywords(textured: bool) -> &'static [&'static str] {
+    if textured {
+        &[]
+    } else {
+        &["SOLID_COLOR"]
+    }
+}
 
 pub struct TestTexture2D {
     translation1: [f32; 3],
     translation2: [f32; 3],
     //
     va: VertexArray,
-    _vb: VertexBuffer,
+    _vb: VertexBuffer<[[f32; 2]; 2]>,
     ib: IndexBuffer,
     //
-    shader: Shader,
-    //
-    proj: glm::Mat4,
+    shaders: Rc<RefCell<ShaderCache>>,
+    textured: bool,
+    /// The variant drawn with, looked up again only when `textured` changes.
+    variant: VariantKey,
     //
     texture1: Texture,
     texture2: Texture,
     //
     renderer: Renderer,
     //
-    timer: std::time::Instant,
+    /// Frames are counted against the wall clock, which pausing or scaling
+    /// the frame clock does not affect.
+    fps_start: Instant,
     fps_counter: u32,
     fps_view: Vec<u8>,
 }
@@ -32,28 +50,44 @@ impl OGLTest for TestTexture2D {
             [[0.5, 0.5], [1.0, 1.0]],
             [[-0.5, 0.5], [0.0, 1.0]],
         ];
-        let indices: &[_] = &[[0, 1, 2], [2, 3, 0]];
+        let indices: &[[u16; 3]] = &[[0, 1, 2], [2, 3, 0]];
         //
         gl_call!(gl::Enable(gl::BLEND));
         gl_call!(gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
         //
-        let vb = VertexBuffer::from(positions);
+        let vb = VertexBuffer::try_from(positions)
+            .unwrap_or_else(|e| panic!("{}", e));
         //
         let mut layout = VertexBufferLayout::new();
         layout.push::<f32>(2);
         layout.push::<f32>(2);
         //
-        let va = VertexArray::new();
-        va.add_buffer(&vb, layout);
-        //
-        let ib = IndexBuffer::from(indices);
+        // Compiles both variants up front so toggling does not stall
+        let shaders = shader_cache();
+        for &textured in &[true, false] {
+            let mut cache = shaders.borrow_mut();
+            let shader = cache
+                .get(SHADER, keywords(textured))
+                .unwrap_or_else(|e| panic!("{}", e));
+            shader
+                .bind_uniform_block::<Camera>("Camera", CAMERA_BINDING)
+                .unwrap_or_else(|e| panic!("{}", e));
+            for mismatch in shader.check_layout(&layout) {
+                println!("Warning: {}", mismatch);
+            }
+        }
         //
-        let shader = Shader::from_file("res/shaders/basic.shader");
+        let va = VertexArray::new().unwrap_or_else(|e| panic!("{}", e));
+        va.add_buffer(&vb, layout)
+            .unwrap_or_else(|e| panic!("{}", e));
         //
-        let proj = glm::ortho(-2.0, 2.0, -1.5, 1.5, -1.0, 1.0);
+        let ib =
+            IndexBuffer::try_from(indices).unwrap_or_else(|e| panic!("{}", e));
         //
-        let tex1 = Texture::from_file("res/textures/mandrill.png");
-        let tex2 = Texture::from_file("res/textures/trans.png");
+        let tex1 = Texture::from_file("res/textures/mandrill.png")
+            .unwrap_or_else(|e| panic!("{}", e));
+        let tex2 = Texture::from_file("res/textures/trans.png")
+            .unwrap_or_else(|e| panic!("{}", e));
         //
         Self {
             translation1: [0.0; 3],
@@ -61,66 +95,87 @@ impl OGLTest for TestTexture2D {
             va: va,
             _vb: vb,
             ib: ib,
-            shader: shader,
-            proj: proj,
+            shaders: shaders,
+            textured: true,
+            variant: VariantKey::new(SHADER, keywords(true)),
             texture1: tex1,
             texture2: tex2,
             renderer: Renderer {},
-            timer: std::time::Instant::now(),
+            fps_start: Instant::now(),
             fps_counter: 0,
             fps_view: "fps: 0".to_owned().into_bytes(),
         }
     }
     //
-    fn on_update(&mut self, _: f32) {
+    fn on_update(&mut self, _delta_time: f32) {
+        self.shaders.borrow_mut().reload_if_changed();
+    }
+    //
+    fn on_render(&mut self) {
         self.fps_counter += 1;
-        if self.timer.elapsed() > std::time::Duration::from_secs(1) {
+        let elapsed = self.fps_start.elapsed().as_secs_f32();
+        if elapsed >= 1.0 {
+            let fps = self.fps_counter as f32 / elapsed;
             self.fps_view.clear();
-            write!(self.fps_view, "fps: {}", self.fps_counter).unwrap();
+            write!(self.fps_view, "fps: {:.0}", fps).unwrap();
             self.fps_counter = 0;
-            self.timer += std::time::Duration::from_secs(1);
+            self.fps_start = Instant::now();
         }
-    }
-    //
-    fn on_render(&mut self) {
+        //
+        let mut shaders = self.shaders.borrow_mut();
+        let shader = match shaders.variant_mut(&self.variant) {
+            Some(shader) => shader,
+            None => return,
+        };
+        //
         {
-            let mvp = self.proj
-                * glm::translate(
-                    &glm::identity(),
-                    &glm::vec3(
-                        self.translation1[0],
-                        self.translation1[1],
-                        self.translation1[2],
-                    ),
-                );
+            let model = glm::translate(
+                &glm::identity(),
+                &glm::vec3(
+                    self.translation1[0],
+                    self.translation1[1],
+                    self.translation1[2],
+                ),
+            );
             //
-            self.shader.bind();
-            self.shader.set_uniform_mat4f("u_mvp\0", &mvp);
+            shader.bind();
+            shader.set("u_model", &model);
+            if !self.textured {
+                shader.set("u_color", &glm::Vec4::new(0.2, 0.3, 0.8, 1.0));
+            }
             //
             self.texture1.bind();
-            self.renderer.draw(&self.va, &self.ib, &self.shader);
+            self.renderer.draw(&self.va, &self.ib, shader);
         }
         //
         {
-            let mvp = self.proj
-                * glm::translate(
-                    &glm::identity(),
-                    &glm::vec3(
-                        self.translation2[0],
-                        self.translation2[1],
-                        self.translation2[2],
-                    ),
-                );
+            let model = glm::translate(
+                &glm::identity(),
+                &glm::vec3(
+                    self.translation2[0],
+                    self.translation2[1],
+                    self.translation2[2],
+                ),
+            );
             //
-            self.shader.bind();
-            self.shader.set_uniform_mat4f("u_mvp\0", &mvp);
+            shader.bind();
+            shader.set("u_model", &model);
+            if !self.textured {
+                shader.set("u_color", &glm::Vec4::new(0.8, 0.3, 0.2, 1.0));
+            }
             //
             self.texture2.bind();
-            self.renderer.draw(&self.va, &self.ib, &self.shader);
+            self.renderer.draw(&self.va, &self.ib, shader);
         }
     }
     //
     fn on_imgui_render(&mut self, ui: &imgui_glfw_rs::imgui::Ui) {
+        for (key, shader) in self.shaders.borrow().variants() {
+            if key.filepath == SHADER {
+                shader_error_overlay(ui, shader);
+            }
+        }
+        //
         ui.text(&std::str::from_utf8(&self.fps_view).unwrap());
         //
         ui.slider_float3(
@@ -138,6 +193,23 @@ impl OGLTest for TestTexture2D {
             1.0,
         )
         .build();
+        //
+        if ui.checkbox(im_str!("Textured"), &mut self.textured) {
+            self.select_variant();
+        }
+        shader_cache_stats(ui, &self.shaders.borrow());
+    }
+}
+
+impl TestTexture2D {
+    /// Switches to the variant for `textured`, keeping the previous one when
+    /// it fails to compile.
+    fn select_variant(&mut self) {
+        let keywords = keywords(self.textured);
+        match self.shaders.borrow_mut().get(SHADER, keywords) {
+            Ok(_) => self.variant = VariantKey::new(SHADER, keywords),
+            Err(e) => println!("{}", e),
+        }
     }
 }
 
@@ -1,19 +1,63 @@
-use super::glcall;
+use super::{gl_backend, glcall};
 
-use std::ffi::c_void;
+use super::GlCallError;
 
 use image;
 
-fn load_img(filepath: &str) -> (u32, u32, Vec<u8>) {
+use std::{error::Error, fmt};
+
+#[derive(Debug)]
+pub enum TextureError {
+    Image {
+        filepath: String,
+        error: image::ImageError,
+    },
+    Gl(GlCallError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Image { filepath, error } => {
+                write!(
+                    f,
+                    "Image: '{}' did not open correctly: {}",
+                    filepath, error
+                )
+            }
+            TextureError::Gl(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TextureError::Image { error, .. } => Some(error),
+            TextureError::Gl(error) => Some(error),
+        }
+    }
+}
+
+impl From<GlCallError> for TextureError {
+    fn from(error: GlCallError) -> Self {
+        TextureError::Gl(error)
+    }
+}
+
+fn load_img(filepath: &str) -> Result<(u32, u32, Vec<u8>), TextureError> {
     let img = image::open(filepath)
-        .expect(&format!("Image: '{}' did not open correctly", filepath))
+        .map_err(|error| TextureError::Image {
+            filepath: filepath.to_owned(),
+            error: error,
+        })?
         .flipv()
         .to_rgba();
     //
     let width = img.width();
     let height = img.height();
     //
-    (width, height, img.to_vec())
+    Ok((width, height, img.to_vec()))
 }
 
 pub struct Texture {
@@ -23,60 +67,162 @@ pub struct Texture {
     pub height: u32,
 }
 
+/// The client-side format and type matching a sized internal format, `None`
+/// for formats textures cannot be created with.
+fn pixel_format(internal_format: u32) -> Option<(u32, u32)> {
+    let format = match internal_format {
+        gl::R8 | gl::R16 | gl::R16F | gl::R32F => gl::RED,
+        gl::RG8 | gl::RG16 | gl::RG16F | gl::RG32F => gl::RG,
+        gl::RGB8
+        | gl::SRGB8
+        | gl::RGB16F
+        | gl::RGB32F
+        | gl::R11F_G11F_B10F
+        | gl::RGB9_E5 => gl::RGB,
+        gl::RGBA8
+        | gl::SRGB8_ALPHA8
+        | gl::RGBA16
+        | gl::RGB10_A2
+        | gl::RGBA16F
+        | gl::RGBA32F => gl::RGBA,
+        gl::R8UI | gl::R8I | gl::R16UI | gl::R16I | gl::R32UI | gl::R32I => {
+            gl::RED_INTEGER
+        }
+        gl::RG8UI
+        | gl::RG8I
+        | gl::RG16UI
+        | gl::RG16I
+        | gl::RG32UI
+        | gl::RG32I => gl::RG_INTEGER,
+        gl::RGB8UI
+        | gl::RGB8I
+        | gl::RGB16UI
+        | gl::RGB16I
+        | gl::RGB32UI
+        | gl::RGB32I => gl::RGB_INTEGER,
+        gl::RGBA8UI
+        | gl::RGBA8I
+        | gl::RGBA16UI
+        | gl::RGBA16I
+        | gl::RGBA32UI
+        | gl::RGBA32I
+        | gl::RGB10_A2UI => gl::RGBA_INTEGER,
+        _ => return None,
+    };
+    let data_type = match internal_format {
+        gl::R16F | gl::RG16F | gl::RGB16F | gl::RGBA16F => gl::FLOAT,
+        gl::R32F | gl::RG32F | gl::RGB32F | gl::RGBA32F => gl::FLOAT,
+        gl::RGB10_A2 | gl::RGB10_A2UI => gl::UNSIGNED_INT_2_10_10_10_REV,
+        gl::R11F_G11F_B10F => gl::UNSIGNED_INT_10F_11F_11F_REV,
+        gl::RGB9_E5 => gl::UNSIGNED_INT_5_9_9_9_REV,
+        gl::R16 | gl::RG16 | gl::RGBA16 => gl::UNSIGNED_SHORT,
+        gl::R16UI | gl::RG16UI | gl::RGB16UI | gl::RGBA16UI => {
+            gl::UNSIGNED_SHORT
+        }
+        gl::R32UI | gl::RG32UI | gl::RGB32UI | gl::RGBA32UI => gl::UNSIGNED_INT,
+        gl::R8I | gl::RG8I | gl::RGB8I | gl::RGBA8I => gl::BYTE,
+        gl::R16I | gl::RG16I | gl::RGB16I | gl::RGBA16I => gl::SHORT,
+        gl::R32I | gl::RG32I | gl::RGB32I | gl::RGBA32I => gl::INT,
+        _ => gl::UNSIGNED_BYTE,
+    };
+    Some((format, data_type))
+}
+
 impl Texture {
-    pub fn from_file(filepath: &str) -> Self {
-        let mut id = 0;
-        gl_call!(gl::GenTextures(1, &mut id));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, id));
+    fn create(
+        filepath: &str,
+        width: u32,
+        height: u32,
+        internal_format: u32,
+        data: Option<&[u8]>,
+    ) -> Result<Self, GlCallError> {
+        let backend = gl_backend::current();
+        let (format, data_type) =
+            pixel_format(internal_format).unwrap_or_else(|| {
+                panic!("unsupported internal format 0x{:x}", internal_format)
+            });
         //
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::LINEAR as i32
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::LINEAR as i32
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_S,
-            gl::CLAMP_TO_EDGE as i32,
-        ));
-        gl_call!(gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_T,
-            gl::CLAMP_TO_EDGE as i32,
-        ));
-        //
-        let (width, height, buffer) = load_img(filepath);
-        //
-        gl_call!(gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGBA8 as i32,
-            width as i32,
-            height as i32,
-            0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            (&buffer).as_ptr() as *const c_void
-        ));
-        //
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        glcall::try_create(|| {
+            let texture = Self {
+                _filepath: filepath.to_owned(),
+                render_id: backend.gen_texture(),
+                width: width,
+                height: height,
+            };
+            backend.bind_texture(gl::TEXTURE_2D, texture.render_id);
+            //
+            backend.tex_parameter_i(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR as i32,
+            );
+            backend.tex_parameter_i(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                gl::LINEAR as i32,
+            );
+            backend.tex_parameter_i(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as i32,
+            );
+            backend.tex_parameter_i(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as i32,
+            );
+            //
+            backend.tex_image_2d(
+                gl::TEXTURE_2D,
+                internal_format,
+                width as i32,
+                height as i32,
+                format,
+                data_type,
+                data,
+            );
+            //
+            backend.bind_texture(gl::TEXTURE_2D, 0);
+            //
+            texture
+        })
+    }
+    //
+    pub fn from_file(filepath: &str) -> Result<Self, TextureError> {
+        let (width, height, buffer) = load_img(filepath)?;
         //
-        Self {
-            _filepath: filepath.to_owned(),
-            render_id: id,
-            width: width,
-            height: height,
-        }
+        Ok(Self::create(
+            filepath,
+            width,
+            height,
+            gl::RGBA8,
+            Some(&buffer),
+        )?)
+    }
+    //
+    /// An uninitialized texture, e.g. to render into through a `Framebuffer`.
+    /// Panics on formats `supports_format` rejects.
+    pub fn new(
+        width: u32,
+        height: u32,
+        internal_format: u32,
+    ) -> Result<Self, GlCallError> {
+        Self::create("", width, height, internal_format, None)
+    }
+    //
+    /// Whether `new` can create a texture with `internal_format`.
+    pub fn supports_format(internal_format: u32) -> bool {
+        pixel_format(internal_format).is_some()
+    }
+    //
+    pub fn renderer_id(&self) -> u32 {
+        self.render_id
     }
     //
     pub fn bind_slot(&self, slot: u32) {
-        gl_call!(gl::ActiveTexture(gl::TEXTURE0 + slot));
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, self.render_id));
+        let backend = gl_backend::current();
+        backend.active_texture(gl::TEXTURE0 + slot);
+        backend.bind_texture(gl::TEXTURE_2D, self.render_id);
     }
     //
     pub fn bind(&self) {
@@ -84,12 +230,44 @@ impl Texture {
     }
     //
     pub fn unbind(&self) {
-        gl_call!(gl::BindTexture(gl::TEXTURE_2D, 0));
+        gl_backend::current().bind_texture(gl::TEXTURE_2D, 0);
     }
 }
 
 impl Drop for Texture {
     fn drop(&mut self) {
-        gl_call!(gl::DeleteTextures(1, &self.render_id))
+        gl_backend::current().delete_texture(self.render_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+
+    #[test]
+    fn texture_calls() {
+        let mock = install_mock();
+        let texture = Texture::new(4, 2, gl::RGBA8).unwrap();
+        texture.bind_slot(3);
+        drop(texture);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "GenTextures(-> 1)",
+                "BindTexture(0xde1, 1)",
+                "TexParameteri(0xde1, 0x2801, 0x2601)",
+                "TexParameteri(0xde1, 0x2800, 0x2601)",
+                "TexParameteri(0xde1, 0x2802, 0x812f)",
+                "TexParameteri(0xde1, 0x2803, 0x812f)",
+                "TexImage2D(0xde1, 0x8058, 4x2, 0x1908, 0x1401, 0 bytes)",
+                "BindTexture(0xde1, 0)",
+                "ActiveTexture(0x84c3)",
+                "BindTexture(0xde1, 1)",
+                "DeleteTextures(1)",
+            ]
+        );
     }
 }
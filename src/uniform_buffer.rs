@@ -0,0 +1,469 @@
+use super::{gl_backend, glcall, GlCallError};
+
+use std::{cell::Cell, error::Error, fmt, marker::PhantomData};
+
+use nalgebra_glm as glm;
+
+const fn round_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+/// A type with a std140 representation, so it can be a member of a uniform
+/// block. Structs get it from `std140_struct!`.
+pub trait Std140 {
+    /// Base alignment in bytes.
+    const ALIGN: usize;
+    /// Size in bytes, without the padding up to the next member.
+    const SIZE: usize;
+    /// Appends `SIZE` bytes at the writer's offset, which is already
+    /// aligned to `ALIGN`.
+    fn write_std140(&self, writer: &mut Std140Writer);
+}
+
+/// Lays out std140 members one after another, inserting the padding the
+/// rules require.
+pub struct Std140Writer {
+    bytes: Vec<u8>,
+}
+
+impl Std140Writer {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+    //
+    pub fn write<T: Std140>(&mut self, value: &T) {
+        self.align(T::ALIGN);
+        value.write_std140(self);
+    }
+    //
+    pub fn align(&mut self, align: usize) {
+        let len = round_up(self.bytes.len(), align);
+        self.bytes.resize(len, 0);
+    }
+    //
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+    //
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for Std140Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The std140 bytes of `value`, padded to a multiple of 16 like a block.
+pub fn std140_bytes<T: Std140>(value: &T) -> Vec<u8> {
+    let mut writer = Std140Writer::new();
+    writer.write(value);
+    writer.align(16);
+    writer.into_bytes()
+}
+
+macro_rules! std140_scalar {
+    ($ty:ty, $to_bytes:expr) => {
+        impl Std140 for $ty {
+            const ALIGN: usize = 4;
+            const SIZE: usize = 4;
+            //
+            fn write_std140(&self, writer: &mut Std140Writer) {
+                writer.write_bytes(&$to_bytes(*self));
+            }
+        }
+    };
+}
+
+std140_scalar!(f32, f32::to_ne_bytes);
+std140_scalar!(i32, i32::to_ne_bytes);
+std140_scalar!(u32, u32::to_ne_bytes);
+std140_scalar!(bool, |v: bool| (v as u32).to_ne_bytes());
+
+/// vec3 is aligned like a vec4 but only 12 bytes long, so a scalar can
+/// follow it directly.
+macro_rules! std140_vector {
+    ($ty:ty, $n:expr, $align:expr) => {
+        impl Std140 for $ty {
+            const ALIGN: usize = $align;
+            const SIZE: usize = 4 * $n;
+            //
+            fn write_std140(&self, writer: &mut Std140Writer) {
+                for v in self.iter() {
+                    v.write_std140(writer);
+                }
+            }
+        }
+    };
+}
+
+std140_vector!(glm::Vec2, 2, 8);
+std140_vector!(glm::Vec3, 3, 16);
+std140_vector!(glm::Vec4, 4, 16);
+std140_vector!(glm::IVec2, 2, 8);
+std140_vector!(glm::IVec3, 3, 16);
+std140_vector!(glm::IVec4, 4, 16);
+std140_vector!(glm::UVec2, 2, 8);
+std140_vector!(glm::UVec3, 3, 16);
+std140_vector!(glm::UVec4, 4, 16);
+
+/// A matrix is stored as an array of its columns, each padded to 16 bytes.
+macro_rules! std140_matrix {
+    ($ty:ty, $n:expr) => {
+        impl Std140 for $ty {
+            const ALIGN: usize = 16;
+            const SIZE: usize = 16 * $n;
+            //
+            fn write_std140(&self, writer: &mut Std140Writer) {
+                for column in self.column_iter() {
+                    for v in column.iter() {
+                        v.write_std140(writer);
+                    }
+                    writer.align(16);
+                }
+            }
+        }
+    };
+}
+
+std140_matrix!(glm::Mat2, 2);
+std140_matrix!(glm::Mat3, 3);
+std140_matrix!(glm::Mat4, 4);
+
+/// Every array element is padded to a multiple of 16 bytes.
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = round_up(T::ALIGN, 16);
+    const SIZE: usize = N * round_up(T::SIZE, 16);
+    //
+    fn write_std140(&self, writer: &mut Std140Writer) {
+        for element in self.iter() {
+            writer.write(element);
+            writer.align(16);
+        }
+    }
+}
+
+/// Declares a struct and implements `Std140` for it, laying the fields out
+/// in declaration order.
+///
+/// ```ignore
+/// std140_struct! {
+///     pub struct Camera {
+///         pub proj: glm::Mat4,
+///         pub view: glm::Mat4,
+///     }
+/// }
+/// ```
+macro_rules! std140_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+        //
+        impl $crate::Std140 for $name {
+            const ALIGN: usize = 16;
+            const SIZE: usize = {
+                let mut offset = 0;
+                $(
+                    let align = <$ty as $crate::Std140>::ALIGN;
+                    offset = (offset + align - 1) / align * align;
+                    offset += <$ty as $crate::Std140>::SIZE;
+                )*
+                (offset + 15) / 16 * 16
+            };
+            //
+            fn write_std140(&self, writer: &mut $crate::Std140Writer) {
+                $(writer.write(&self.$field);)*
+                writer.align(16);
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UniformBlockError {
+    MissingBlock {
+        block: String,
+    },
+    SizeMismatch {
+        block: String,
+        block_size: usize,
+        struct_size: usize,
+    },
+}
+
+impl fmt::Display for UniformBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UniformBlockError::MissingBlock { block } => {
+                write!(f, "uniform block '{}' is not active", block)
+            }
+            UniformBlockError::SizeMismatch {
+                block,
+                block_size,
+                struct_size,
+            } => write!(
+                f,
+                "uniform block '{}' is {} bytes, but the std140 struct is {}",
+                block, block_size, struct_size
+            ),
+        }
+    }
+}
+
+impl Error for UniformBlockError {}
+
+/// Checks a block of `block_size` bytes can be backed by a std140 struct of
+/// `struct_size` bytes. Drivers may or may not pad the block size to 16
+/// bytes, so both are compared padded.
+pub fn check_block_size(
+    block: &str,
+    block_size: usize,
+    struct_size: usize,
+) -> Result<(), UniformBlockError> {
+    if round_up(block_size, 16) == round_up(struct_size, 16) {
+        Ok(())
+    } else {
+        Err(UniformBlockError::SizeMismatch {
+            block: block.to_owned(),
+            block_size: block_size,
+            struct_size: struct_size,
+        })
+    }
+}
+
+thread_local! {
+    static USED_BINDINGS: Cell<u64> = Cell::new(0);
+}
+
+fn claim_binding(binding: u32) {
+    assert!(
+        binding < 64,
+        "uniform buffer binding {} is too high",
+        binding
+    );
+    USED_BINDINGS.with(|used| {
+        let mask = 1u64 << binding;
+        assert!(
+            used.get() & mask == 0,
+            "uniform buffer binding {} is already in use",
+            binding
+        );
+        used.set(used.get() | mask);
+    });
+}
+
+fn release_binding(binding: u32) {
+    USED_BINDINGS.with(|used| used.set(used.get() & !(1u64 << binding)));
+}
+
+/// The lowest binding point no `UniformBuffer` is using.
+fn free_binding() -> u32 {
+    let max = gl_backend::current()
+        .get_integer(gl::MAX_UNIFORM_BUFFER_BINDINGS)
+        .min(64) as u32;
+    let used = USED_BINDINGS.with(|used| used.get());
+    (0..max)
+        .find(|binding| used & (1u64 << binding) == 0)
+        .expect("no free uniform buffer binding points")
+}
+
+/// A buffer holding one `T` in std140 layout, bound to its own binding
+/// point for as long as it lives. Shaders read it through
+/// `Shader::bind_uniform_block`.
+pub struct UniformBuffer<T: Std140> {
+    renderer_id: u32,
+    binding: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+    /// Uses the lowest free binding point.
+    pub fn new(value: &T) -> Result<Self, GlCallError> {
+        Self::with_binding(free_binding(), value)
+    }
+    //
+    /// Panics if another `UniformBuffer` already uses `binding`.
+    pub fn with_binding(binding: u32, value: &T) -> Result<Self, GlCallError> {
+        claim_binding(binding);
+        //
+        let backend = gl_backend::current();
+        glcall::try_create(|| {
+            let ub = Self {
+                renderer_id: backend.gen_buffer(),
+                binding: binding,
+                _marker: PhantomData,
+            };
+            backend.bind_buffer(gl::UNIFORM_BUFFER, ub.renderer_id);
+            backend.buffer_data(
+                gl::UNIFORM_BUFFER,
+                &std140_bytes(value),
+                gl::DYNAMIC_DRAW,
+            );
+            backend.bind_buffer(gl::UNIFORM_BUFFER, 0);
+            backend.bind_buffer_base(
+                gl::UNIFORM_BUFFER,
+                binding,
+                ub.renderer_id,
+            );
+            ub
+        })
+    }
+    //
+    pub fn update(&self, value: &T) {
+        let backend = gl_backend::current();
+        backend.bind_buffer(gl::UNIFORM_BUFFER, self.renderer_id);
+        backend.buffer_sub_data(gl::UNIFORM_BUFFER, 0, &std140_bytes(value));
+        backend.bind_buffer(gl::UNIFORM_BUFFER, 0);
+    }
+    //
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+    //
+    pub fn renderer_id(&self) -> u32 {
+        self.renderer_id
+    }
+}
+
+impl<T: Std140> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        let backend = gl_backend::current();
+        backend.bind_buffer_base(gl::UNIFORM_BUFFER, self.binding, 0);
+        backend.delete_buffer(self.renderer_id);
+        release_binding(self.binding);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+    use super::super::GlError;
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    }
+
+    std140_struct! {
+        struct AfterVec3 {
+            v: glm::Vec3,
+            s: f32,
+        }
+    }
+
+    std140_struct! {
+        struct BeforeVec3 {
+            s: f32,
+            v: glm::Vec3,
+        }
+    }
+
+    std140_struct! {
+        struct Inner {
+            a: f32,
+        }
+    }
+
+    std140_struct! {
+        struct Outer {
+            x: f32,
+            inner: Inner,
+            y: f32,
+        }
+    }
+
+    #[test]
+    fn vec3_is_aligned_like_vec4() {
+        assert_eq!(AfterVec3::SIZE, 16);
+        let value = AfterVec3 {
+            v: glm::vec3(1.0, 2.0, 3.0),
+            s: 4.0,
+        };
+        assert_eq!(floats(&std140_bytes(&value)), [1.0, 2.0, 3.0, 4.0]);
+        //
+        assert_eq!(BeforeVec3::SIZE, 32);
+        let value = BeforeVec3 {
+            s: 4.0,
+            v: glm::vec3(1.0, 2.0, 3.0),
+        };
+        assert_eq!(
+            floats(&std140_bytes(&value)),
+            [4.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn arrays_and_matrices_have_16_byte_stride() {
+        assert_eq!(<[f32; 3]>::ALIGN, 16);
+        assert_eq!(<[f32; 3]>::SIZE, 48);
+        let bytes = std140_bytes(&[1.0f32, 2.0, 3.0]);
+        let expected = [1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0, 0.0];
+        assert_eq!(floats(&bytes)[..10], expected);
+        assert_eq!(bytes.len(), 48);
+        //
+        assert_eq!(glm::Mat3::SIZE, 48);
+        let m = glm::Mat3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0);
+        assert_eq!(
+            floats(&std140_bytes(&m)),
+            [1.0, 4.0, 7.0, 0.0, 2.0, 5.0, 8.0, 0.0, 3.0, 6.0, 9.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn nested_struct_starts_on_16_bytes() {
+        assert_eq!(Inner::SIZE, 16);
+        assert_eq!(Outer::SIZE, 48);
+        let value = Outer {
+            x: 1.0,
+            inner: Inner { a: 2.0 },
+            y: 3.0,
+        };
+        let floats = floats(&std140_bytes(&value));
+        assert_eq!(floats.len(), 12);
+        assert_eq!((floats[0], floats[4], floats[8]), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn block_size_is_compared_padded() {
+        assert_eq!(check_block_size("b", 16, 16), Ok(()));
+        assert_eq!(check_block_size("b", 12, 16), Ok(()));
+        assert_eq!(
+            check_block_size("b", 32, 16),
+            Err(UniformBlockError::SizeMismatch {
+                block: "b".to_owned(),
+                block_size: 32,
+                struct_size: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn failed_uniform_buffer_is_deleted() {
+        let mock = install_mock();
+        mock.fail_on("BufferData", GlError::OutOfMemory);
+        let error = UniformBuffer::with_binding(3, &Inner { a: 1.0 })
+            .err()
+            .unwrap();
+        assert_eq!(error.errors, [GlError::OutOfMemory]);
+        let log = call_log(&mock);
+        assert_eq!(
+            log[log.len() - 2..],
+            ["BindBufferBase(0x8a11, 3, 0)", "DeleteBuffers(1)"]
+        );
+        // panics if the binding point was not released again
+        claim_binding(3);
+    }
+}
@@ -1,60 +1,121 @@
-use super::glcall;
+use super::{gl_backend, glcall};
 
-use super::VertexBuffer;
 use super::VertexBufferLayout;
+use super::{AnyVertexBuffer, GlCallError};
 
-use std::ffi::c_void;
+use std::{error::Error, fmt};
+
+/// A layout whose stride is not the size of the buffer's element type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrideMismatch {
+    pub stride: usize,
+    pub element_size: usize,
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for StrideMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "layout stride of {} bytes does not match the {} bytes of {}",
+            self.stride, self.element_size, self.type_name
+        )
+    }
+}
+
+impl Error for StrideMismatch {}
 
 pub struct VertexArray {
     renderer_id: u32,
 }
 
 impl VertexArray {
-    pub fn new() -> Self {
-        let mut id = 0;
-        gl_call!(gl::GenVertexArrays(1, &mut id));
-        Self { renderer_id: id }
+    pub fn new() -> Result<Self, GlCallError> {
+        glcall::try_create(|| Self {
+            renderer_id: gl_backend::current().gen_vertex_array(),
+        })
     }
     //
     pub fn bind(&self) {
-        gl_call!(gl::BindVertexArray(self.renderer_id));
+        gl_backend::current().bind_vertex_array(self.renderer_id);
     }
     //
     pub fn unbind(&self) {
-        gl_call!(gl::BindVertexArray(0));
+        gl_backend::current().bind_vertex_array(0);
     }
     //
-    pub fn add_buffer(&self, vb: &VertexBuffer, layout: VertexBufferLayout) {
+    /// Fails without touching GL if the stride of `layout` is not the size
+    /// of the buffer's element type.
+    pub fn add_buffer(
+        &self,
+        vb: &AnyVertexBuffer,
+        layout: VertexBufferLayout,
+    ) -> Result<(), StrideMismatch> {
+        if layout.get_stride() as usize != vb.element_size() {
+            return Err(StrideMismatch {
+                stride: layout.get_stride() as usize,
+                element_size: vb.element_size(),
+                type_name: vb.type_name(),
+            });
+        }
         // return;
         self.bind();
         vb.bind();
         // let elements = layout.get_elements();
         //
+        let backend = gl_backend::current();
         let mut offset = 0;
         //
         for (i, element) in layout.get_elements().iter().enumerate() {
             let i = i as u32;
             //
-            gl_call!(gl::EnableVertexAttribArray(i));
-            gl_call!(gl::VertexAttribPointer(
+            backend.enable_vertex_attrib_array(i);
+            backend.vertex_attrib_pointer(
                 i,
                 element.count,
                 element.el_type,
-                if element.normalized {
-                    gl::TRUE
-                } else {
-                    gl::FALSE
-                },
+                element.normalized,
                 layout.get_stride(),
-                offset as *const c_void,
-            ));
+                offset as usize,
+            );
             offset += element.count * element.type_size();
         }
+        Ok(())
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
-        gl_call!(gl::DeleteVertexArrays(1, &self.renderer_id));
+        gl_backend::current().delete_vertex_array(self.renderer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+    use super::super::VertexBuffer;
+
+    use std::convert::TryFrom;
+
+    #[test]
+    fn vertex_array_rejects_stride_mismatch() {
+        let mock = install_mock();
+        let vb = VertexBuffer::try_from(&[[0.0f32; 2]; 3][..]).unwrap();
+        let va = VertexArray::new().unwrap();
+        let mut layout = VertexBufferLayout::new();
+        layout.push::<f32>(3);
+        mock.clear_calls();
+        //
+        assert_eq!(
+            va.add_buffer(&vb, layout),
+            Err(StrideMismatch {
+                stride: 12,
+                element_size: 8,
+                type_name: "[f32; 2]",
+            })
+        );
+        assert_eq!(call_log(&mock), Vec::<String>::new());
     }
 }
@@ -1,39 +1,597 @@
-use super::glcall;
+use super::{gl_backend, glcall};
 
-use std::ffi::c_void;
+use super::{GlCallError, MapAccess, MappedRange, MappedRangeMut};
 
-pub struct VertexBuffer {
+use std::{
+    any::{type_name, TypeId},
+    convert::TryFrom,
+    marker::PhantomData,
+    mem::size_of,
+    ops::{Deref, DerefMut, Range},
+};
+
+/// Flags for persistently mapped storage that is written by the CPU and
+/// seen by the GPU without explicit flushes.
+const PERSISTENT_FLAGS: u32 =
+    gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+
+/// How often the contents of a buffer are expected to change, passed to the
+/// driver as the usage hint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times.
+    Static,
+    /// Updated now and then and drawn many times.
+    Dynamic,
+    /// Rewritten about every time it is drawn.
+    Stream,
+}
+
+impl BufferUsage {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// A vertex buffer that has forgotten its element type, so buffers of
+/// different vertex types can be stored together. It still knows the size
+/// of an element and how many have been written.
+pub struct AnyVertexBuffer {
     renderer_id: u32,
+    usage: BufferUsage,
+    /// Allocated bytes.
+    capacity: usize,
+    /// Bytes holding vertex data, from the start of the buffer.
+    byte_len: usize,
+    element_size: usize,
+    type_id: TypeId,
+    type_name: &'static str,
+    /// The mapping of immutable persistent storage, which is never
+    /// reallocated or unmapped.
+    persistent: Option<*mut u8>,
 }
 
-impl VertexBuffer {
+impl AnyVertexBuffer {
     pub fn bind(&self) {
-        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, self.renderer_id));
+        gl_backend::current().bind_buffer(gl::ARRAY_BUFFER, self.renderer_id);
     }
     pub fn unbind(&self) {
-        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, 0));
+        gl_backend::current().bind_buffer(gl::ARRAY_BUFFER, 0);
     }
-}
-
-impl<T> From<&[T]> for VertexBuffer {
-    fn from(data: &[T]) -> Self {
-        let mut id = 0;
+    //
+    /// Gives the buffer fresh storage of the same capacity and discards its
+    /// contents.
+    pub fn orphan(&mut self) {
+        assert!(
+            self.persistent.is_none(),
+            "persistent buffers cannot be orphaned"
+        );
+        self.bind();
+        gl_backend::current().allocate_buffer(
+            gl::ARRAY_BUFFER,
+            self.capacity,
+            self.usage.gl_enum(),
+        );
+        self.byte_len = 0;
+    }
+    //
+    /// Reallocates to at least `min_capacity` bytes, at least doubling the
+    /// capacity. The contents are kept by copying them through a temporary
+    /// buffer, so the id and with it every vertex array using the buffer
+    /// stays valid.
+    fn grow(&mut self, min_capacity: usize) {
+        assert!(
+            self.persistent.is_none(),
+            "persistent buffers cannot grow past {} bytes",
+            self.capacity
+        );
+        let backend = gl_backend::current();
+        let capacity = min_capacity.max(self.capacity * 2);
+        //
+        let tmp = if self.byte_len > 0 {
+            let tmp = backend.gen_buffer();
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, tmp);
+            backend.allocate_buffer(
+                gl::COPY_WRITE_BUFFER,
+                self.byte_len,
+                gl::STREAM_COPY,
+            );
+            backend.bind_buffer(gl::COPY_READ_BUFFER, self.renderer_id);
+            backend.copy_buffer_sub_data(
+                gl::COPY_READ_BUFFER,
+                gl::COPY_WRITE_BUFFER,
+                0,
+                0,
+                self.byte_len,
+            );
+            Some(tmp)
+        } else {
+            None
+        };
         //
-        gl_call!(gl::GenBuffers(1, &mut id));
-        gl_call!(gl::BindBuffer(gl::ARRAY_BUFFER, id));
-        gl_call!(gl::BufferData(
+        self.bind();
+        backend.allocate_buffer(
             gl::ARRAY_BUFFER,
-            (data.len() * std::mem::size_of::<T>()) as isize,
-            data.as_ptr() as *mut c_void,
-            gl::STATIC_DRAW,
-        ));
+            capacity,
+            self.usage.gl_enum(),
+        );
+        self.capacity = capacity;
         //
-        Self { renderer_id: id }
+        if let Some(tmp) = tmp {
+            backend.bind_buffer(gl::COPY_READ_BUFFER, tmp);
+            backend.copy_buffer_sub_data(
+                gl::COPY_READ_BUFFER,
+                gl::ARRAY_BUFFER,
+                0,
+                0,
+                self.byte_len,
+            );
+            backend.bind_buffer(gl::COPY_READ_BUFFER, 0);
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+            backend.delete_buffer(tmp);
+        }
+    }
+    //
+    /// Writes `data` at byte `offset`, growing the buffer first when it
+    /// does not fit.
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        let end = offset + data.len();
+        if end > self.capacity {
+            self.grow(end);
+        }
+        //
+        if let Some(ptr) = self.persistent {
+            unsafe {
+                let dst = std::slice::from_raw_parts_mut(ptr, self.capacity);
+                dst[offset..end].copy_from_slice(data);
+            }
+            return;
+        }
+        self.bind();
+        gl_backend::current().buffer_sub_data(gl::ARRAY_BUFFER, offset, data);
+        self.byte_len = self.byte_len.max(end);
+    }
+    //
+    /// Gets the typed buffer back, or `self` if it holds another type.
+    pub fn downcast<T: 'static>(self) -> Result<VertexBuffer<T>, Self> {
+        if self.type_id == TypeId::of::<T>() {
+            Ok(VertexBuffer {
+                buffer: self,
+                _marker: PhantomData,
+            })
+        } else {
+            Err(self)
+        }
+    }
+    //
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+    //
+    /// Allocated bytes.
+    pub fn byte_capacity(&self) -> usize {
+        self.capacity
+    }
+    //
+    /// Bytes written so far.
+    pub fn byte_len(&self) -> usize {
+        self.byte_len
+    }
+    //
+    /// `size_of` the element type, which is also the stride of its layout.
+    pub fn element_size(&self) -> usize {
+        self.element_size
+    }
+    //
+    /// The name of the element type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+    //
+    /// Elements written so far.
+    pub fn count(&self) -> usize {
+        self.byte_len.checked_div(self.element_size).unwrap_or(0)
     }
 }
 
-impl Drop for VertexBuffer {
+impl Drop for AnyVertexBuffer {
     fn drop(&mut self) {
-        gl_call!(gl::DeleteBuffers(1, &self.renderer_id));
+        gl_backend::current().delete_buffer(self.renderer_id);
+    }
+}
+
+/// A vertex buffer of `T`s. It derefs to `AnyVertexBuffer` for everything
+/// that does not need the element type, and `into_any` erases it.
+pub struct VertexBuffer<T: 'static> {
+    buffer: AnyVertexBuffer,
+    _marker: PhantomData<T>,
+}
+
+impl<T: 'static> VertexBuffer<T> {
+    fn allocate(
+        usage: BufferUsage,
+        data: Option<&[T]>,
+        capacity: usize,
+    ) -> Result<Self, GlCallError> {
+        let backend = gl_backend::current();
+        //
+        glcall::try_create(|| {
+            let id = backend.gen_buffer();
+            let buffer = Self {
+                buffer: AnyVertexBuffer {
+                    renderer_id: id,
+                    usage: usage,
+                    capacity: size_of::<T>() * capacity,
+                    byte_len: size_of::<T>() * data.map_or(0, |d| d.len()),
+                    element_size: size_of::<T>(),
+                    type_id: TypeId::of::<T>(),
+                    type_name: type_name::<T>(),
+                    persistent: None,
+                },
+                _marker: PhantomData,
+            };
+            backend.bind_buffer(gl::ARRAY_BUFFER, id);
+            match data {
+                Some(data) => backend.buffer_data(
+                    gl::ARRAY_BUFFER,
+                    gl_backend::as_bytes(data),
+                    usage.gl_enum(),
+                ),
+                None => backend.allocate_buffer(
+                    gl::ARRAY_BUFFER,
+                    size_of::<T>() * capacity,
+                    usage.gl_enum(),
+                ),
+            }
+            buffer
+        })
+    }
+    //
+    pub fn with_usage(
+        data: &[T],
+        usage: BufferUsage,
+    ) -> Result<Self, GlCallError> {
+        Self::allocate(usage, Some(data), data.len())
+    }
+    //
+    /// Allocates room for `capacity` elements without filling it.
+    pub fn with_capacity(
+        capacity: usize,
+        usage: BufferUsage,
+    ) -> Result<Self, GlCallError> {
+        Self::allocate(usage, None, capacity)
+    }
+    //
+    /// Immutable storage for `capacity` elements that stays mapped for as
+    /// long as the buffer lives, so the CPU can write it while the GPU
+    /// draws from other parts of it. Synchronizing the two is up to the
+    /// caller, see `RingBuffer`. `None` without GL 4.4 or
+    /// `GL_ARB_buffer_storage`, or if the storage cannot be mapped.
+    pub fn persistent(capacity: usize) -> Option<Self> {
+        let backend = gl_backend::current();
+        let size = size_of::<T>() * capacity;
+        //
+        let created = glcall::try_create(|| {
+            let id = backend.gen_buffer();
+            let mut buffer = Self {
+                buffer: AnyVertexBuffer {
+                    renderer_id: id,
+                    usage: BufferUsage::Stream,
+                    capacity: size,
+                    byte_len: size,
+                    element_size: size_of::<T>(),
+                    type_id: TypeId::of::<T>(),
+                    type_name: type_name::<T>(),
+                    persistent: None,
+                },
+                _marker: PhantomData,
+            };
+            backend.bind_buffer(gl::ARRAY_BUFFER, id);
+            let stored = backend.buffer_storage(
+                gl::ARRAY_BUFFER,
+                size,
+                PERSISTENT_FLAGS,
+            );
+            if stored {
+                let ptr = backend.map_buffer_range(
+                    gl::ARRAY_BUFFER,
+                    0,
+                    size,
+                    PERSISTENT_FLAGS,
+                );
+                if !ptr.is_null() {
+                    buffer.buffer.persistent = Some(ptr as *mut u8);
+                }
+            }
+            buffer
+        });
+        // A buffer that did not get mapped is deleted as it drops
+        match created {
+            Ok(buffer) if buffer.buffer.persistent.is_some() => Some(buffer),
+            Ok(_) => None,
+            Err(e) => {
+                println!(
+                    "Warning: failed to create a persistent buffer: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+    //
+    /// The whole mapping of a `persistent` buffer.
+    pub fn persistent_slice(&mut self) -> Option<&mut [T]> {
+        let len = self.capacity();
+        self.buffer.persistent.map(|ptr| unsafe {
+            std::slice::from_raw_parts_mut(ptr as *mut T, len)
+        })
+    }
+    //
+    /// Maps the elements in `range` for reading. The range has to lie
+    /// within the capacity. Persistent buffers are already mapped, see
+    /// `persistent_slice`.
+    pub fn map_range(&self, range: Range<usize>) -> MappedRange<'_, T> {
+        self.check_map_range(&range);
+        MappedRange::new(
+            self.buffer.renderer_id,
+            range.start,
+            range.len(),
+            MapAccess::Read,
+        )
+    }
+    //
+    /// Maps the elements in `range` for `MapAccess::Write` or
+    /// `MapAccess::ReadWrite`.
+    pub fn map_range_mut(
+        &mut self,
+        range: Range<usize>,
+        access: MapAccess,
+    ) -> MappedRangeMut<'_, T> {
+        self.check_map_range(&range);
+        let end = size_of::<T>() * range.end;
+        self.buffer.byte_len = self.buffer.byte_len.max(end);
+        MappedRangeMut::new(
+            self.buffer.renderer_id,
+            range.start,
+            range.len(),
+            access,
+        )
+    }
+    //
+    fn check_map_range(&self, range: &Range<usize>) {
+        assert!(
+            self.buffer.persistent.is_none(),
+            "persistent buffers are always mapped"
+        );
+        assert!(
+            range.start <= range.end && range.end <= self.capacity(),
+            "range {:?} is outside a buffer of {} elements",
+            range,
+            self.capacity()
+        );
+    }
+    //
+    /// Writes `data` starting `offset` elements into the buffer, growing it
+    /// first when it does not fit.
+    pub fn update(&mut self, offset: usize, data: &[T]) {
+        self.buffer
+            .write(size_of::<T>() * offset, gl_backend::as_bytes(data));
+    }
+    //
+    /// Replaces the whole contents with `data`. The old storage is orphaned
+    /// first, so the driver does not have to wait for draws still reading
+    /// it.
+    pub fn replace(&mut self, data: &[T]) {
+        let len = std::mem::size_of_val(data);
+        self.buffer.capacity = self.buffer.capacity.max(len);
+        self.buffer.orphan();
+        self.buffer.write(0, gl_backend::as_bytes(data));
+    }
+    //
+    /// Allocated elements.
+    pub fn capacity(&self) -> usize {
+        self.buffer
+            .capacity
+            .checked_div(size_of::<T>())
+            .unwrap_or(0)
+    }
+    //
+    pub fn into_any(self) -> AnyVertexBuffer {
+        self.buffer
+    }
+}
+
+impl<T: 'static> Deref for VertexBuffer<T> {
+    type Target = AnyVertexBuffer;
+    //
+    fn deref(&self) -> &AnyVertexBuffer {
+        &self.buffer
+    }
+}
+
+impl<T: 'static> DerefMut for VertexBuffer<T> {
+    fn deref_mut(&mut self) -> &mut AnyVertexBuffer {
+        &mut self.buffer
+    }
+}
+
+impl<T: 'static> TryFrom<&[T]> for VertexBuffer<T> {
+    type Error = GlCallError;
+    //
+    fn try_from(data: &[T]) -> Result<Self, GlCallError> {
+        Self::with_usage(data, BufferUsage::Static)
+    }
+}
+
+impl<T: 'static> From<VertexBuffer<T>> for AnyVertexBuffer {
+    fn from(buffer: VertexBuffer<T>) -> Self {
+        buffer.into_any()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::gl_backend::tests::{call_log, install_mock};
+    use super::super::GlError;
+
+    #[test]
+    fn vertex_buffer_calls() {
+        let mock = install_mock();
+        drop(VertexBuffer::try_from(&[[0.0f32; 2]; 3][..]).unwrap());
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "GenBuffers(-> 1)",
+                "BindBuffer(0x8892, 1)",
+                "BufferData(0x8892, 24 bytes, 0x88e4)",
+                "DeleteBuffers(1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn growing_copies_existing_data() {
+        let mock = install_mock();
+        let mut vb =
+            VertexBuffer::with_usage(&[[0.0f32; 2]; 2], BufferUsage::Dynamic)
+                .unwrap();
+        mock.clear_calls();
+        vb.update(2, &[[1.0; 2]; 3]);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "GenBuffers(-> 2)",
+                "BindBuffer(0x8f37, 2)",
+                "BufferData(0x8f37, 16 bytes, null, 0x88e2)",
+                "BindBuffer(0x8f36, 1)",
+                "CopyBufferSubData(0x8f36, 0x8f37, 0, 0, 16)",
+                "BindBuffer(0x8892, 1)",
+                "BufferData(0x8892, 40 bytes, null, 0x88e8)",
+                "BindBuffer(0x8f36, 2)",
+                "CopyBufferSubData(0x8f36, 0x8892, 0, 0, 16)",
+                "BindBuffer(0x8f36, 0)",
+                "BindBuffer(0x8f37, 0)",
+                "DeleteBuffers(2)",
+                "BindBuffer(0x8892, 1)",
+                "BufferSubData(0x8892, 16, 24 bytes)",
+            ]
+        );
+        assert_eq!(vb.byte_len(), 40);
+        assert_eq!(vb.count(), 5);
+        assert_eq!(vb.capacity(), 5);
+    }
+
+    #[test]
+    fn replace_larger_than_capacity() {
+        let mock = install_mock();
+        let mut vb =
+            VertexBuffer::with_capacity(2, BufferUsage::Dynamic).unwrap();
+        mock.clear_calls();
+        vb.replace(&[[1.0f32; 2]; 3]);
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "BindBuffer(0x8892, 1)",
+                "BufferData(0x8892, 24 bytes, null, 0x88e8)",
+                "BindBuffer(0x8892, 1)",
+                "BufferSubData(0x8892, 0, 24 bytes)",
+            ]
+        );
+        assert_eq!(vb.byte_len(), 24);
+        assert_eq!(vb.count(), 3);
+        assert_eq!(vb.capacity(), 3);
+    }
+
+    #[test]
+    fn mapped_range_is_writable_and_aligned() {
+        let mock = install_mock();
+        let mut vb =
+            VertexBuffer::<[f64; 2]>::with_capacity(4, BufferUsage::Dynamic)
+                .unwrap();
+        mock.clear_calls();
+        {
+            let mut range = vb.map_range_mut(1..3, MapAccess::Write);
+            range[1] = [1.0, 2.0];
+            assert_eq!(range[1], [1.0, 2.0]);
+        }
+        //
+        assert_eq!(
+            call_log(&mock),
+            [
+                "BindBuffer(0x8f37, 1)",
+                "MapBufferRange(0x8f37, 16, 32, 0x6)",
+                "BindBuffer(0x8f37, 1)",
+                "UnmapBuffer(0x8f37)",
+                "BindBuffer(0x8f37, 0)",
+            ]
+        );
+        assert_eq!(vb.count(), 3);
+    }
+
+    #[test]
+    fn empty_range_is_not_mapped() {
+        let mock = install_mock();
+        let vb =
+            VertexBuffer::with_usage(&[0u32; 4], BufferUsage::Dynamic).unwrap();
+        mock.clear_calls();
+        assert!(vb.map_range(2..2).is_empty());
+        //
+        assert_eq!(call_log(&mock), Vec::<String>::new());
+    }
+
+    #[test]
+    fn persistent_without_buffer_storage() {
+        let mock = install_mock();
+        mock.fail_on("BufferStorage", GlError::InvalidOperation);
+        //
+        assert!(VertexBuffer::<[f32; 2]>::persistent(16).is_none());
+        assert_eq!(
+            call_log(&mock),
+            [
+                "GenBuffers(-> 1)",
+                "BindBuffer(0x8892, 1)",
+                "BufferStorage(0x8892, 128 bytes, 0xc2)",
+                "DeleteBuffers(1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_persistent_map_deletes_buffer() {
+        let mock = install_mock();
+        mock.fail_on("MapBufferRange", GlError::OutOfMemory);
+        //
+        assert!(VertexBuffer::<[f32; 2]>::persistent(16).is_none());
+        assert_eq!(
+            call_log(&mock)[2..],
+            [
+                "BufferStorage(0x8892, 128 bytes, 0xc2)",
+                "MapBufferRange(0x8892, 0, 128, 0xc2)",
+                "DeleteBuffers(1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn constructor_reports_gl_error() {
+        let mock = install_mock();
+        mock.fail_on("BufferData", GlError::OutOfMemory);
+        let error = match VertexBuffer::try_from(&[0.0f32; 3][..]) {
+            Ok(_) => panic!("the failed BufferData was not reported"),
+            Err(error) => error,
+        };
+        //
+        assert_eq!(error.errors, [GlError::OutOfMemory]);
+        assert_eq!(error.call, "BufferData");
+        assert_eq!(mock.call_names().last(), Some(&"DeleteBuffers"));
     }
 }